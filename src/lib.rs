@@ -228,22 +228,598 @@ pub mod tests {
         weak::weak_ex();
     }
 
-    mod weak {
+    #[test]
+    pub fn weak_tree() {
+        weak::tree_example();
+    }
+
+    #[test]
+    pub fn weak_tree_traversal() {
+        weak::traversal_example();
+    }
+
+    #[test]
+    #[should_panic(expected = "own ancestor")]
+    pub fn weak_tree_no_cycles() {
+        weak::cycle_example();
+    }
+
+    #[test]
+    pub fn weak_tree_outline() {
+        weak::outline_example();
+    }
+
+    #[test]
+    pub fn weak_tree_sexpr() {
+        weak::sexpr_example();
+    }
+
+    pub mod weak {
+        use super::{escape, unescape};
         use std::cell::RefCell;
+        use std::collections::VecDeque;
+        use std::fmt::Display;
         use std::rc::{Rc, Weak};
+        use std::str::FromStr;
 
+        //Parents own their children, children only point back with Weak
+        // so dropping the root frees the whole tree.
         #[derive(Debug)]
-        struct Node {
-            _value: i32,
-            parent: RefCell<Weak<Node>>,
-            _children: RefCell<Vec<Rc<Node>>>,
+        pub struct Node<T> {
+            pub value: T,
+            parent: RefCell<Weak<Node<T>>>,
+            children: RefCell<Vec<Rc<Node<T>>>>,
+        }
+
+        impl<T> Node<T> {
+            pub fn new(value: T) -> Rc<Node<T>> {
+                Rc::new(Node {
+                    value,
+                    parent: RefCell::new(Weak::new()),
+                    children: RefCell::new(vec![]),
+                })
+            }
+
+            //Moves child here if it already has a parent.
+            //Panics if child is self or one of its ancestors.
+            pub fn add_child(self: &Rc<Self>, child: Rc<Node<T>>) {
+                assert!(
+                    !Rc::ptr_eq(self, &child) && !self.ancestors().any(|n| Rc::ptr_eq(&n, &child)),
+                    "can not add a node as a child of itself or its own ancestor"
+                );
+                child.detach();
+                *child.parent.borrow_mut() = Rc::downgrade(self);
+                self.children.borrow_mut().push(child);
+            }
+
+            //Remove from the parent, the node keeps its own children.
+            pub fn detach(self: &Rc<Self>) {
+                if let Some(parent) = self.parent() {
+                    parent
+                        .children
+                        .borrow_mut()
+                        .retain(|c| !Rc::ptr_eq(c, self));
+                }
+                *self.parent.borrow_mut() = Weak::new();
+            }
+
+            pub fn parent(&self) -> Option<Rc<Node<T>>> {
+                self.parent.borrow().upgrade()
+            }
+
+            pub fn children(&self) -> Vec<Rc<Node<T>>> {
+                self.children.borrow().clone()
+            }
+
+            //Parent first, root last.
+            pub fn ancestors(&self) -> Ancestors<T> {
+                Ancestors {
+                    next: self.parent(),
+                }
+            }
+
+            pub fn depth(&self) -> usize {
+                self.ancestors().count()
+            }
+
+            //self first, root last.
+            pub fn path_to_root(self: &Rc<Self>) -> Vec<Rc<Node<T>>> {
+                let mut path = vec![Rc::clone(self)];
+                path.extend(self.ancestors());
+                path
+            }
+
+            //Pre-order, self first.
+            pub fn depth_first(self: &Rc<Self>) -> DepthFirst<T> {
+                DepthFirst {
+                    stack: vec![Rc::clone(self)],
+                }
+            }
+
+            //Level by level, self first.
+            pub fn breadth_first(self: &Rc<Self>) -> BreadthFirst<T> {
+                BreadthFirst {
+                    queue: VecDeque::from([Rc::clone(self)]),
+                }
+            }
+        }
+
+        pub struct Ancestors<T> {
+            next: Option<Rc<Node<T>>>,
+        }
+
+        impl<T> Iterator for Ancestors<T> {
+            type Item = Rc<Node<T>>;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                let node = self.next.take()?;
+                self.next = node.parent();
+                Some(node)
+            }
+        }
+
+        pub struct DepthFirst<T> {
+            stack: Vec<Rc<Node<T>>>,
+        }
+
+        impl<T> Iterator for DepthFirst<T> {
+            type Item = Rc<Node<T>>;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                let node = self.stack.pop()?;
+                //Reversed so the first child is popped next.
+                self.stack
+                    .extend(node.children.borrow().iter().rev().cloned());
+                Some(node)
+            }
+        }
+
+        pub struct BreadthFirst<T> {
+            queue: VecDeque<Rc<Node<T>>>,
+        }
+
+        impl<T> Iterator for BreadthFirst<T> {
+            type Item = Rc<Node<T>>;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                let node = self.queue.pop_front()?;
+                self.queue.extend(node.children.borrow().iter().cloned());
+                Some(node)
+            }
+        }
+
+        //Text forms of a tree. Values are written with Display and read
+        // back with FromStr, the parent links are rebuilt with add_child.
+        //Outline, one node per line indented by one tab per level with tabs
+        // and newlines in values escaped. An empty value is written as \e so
+        // blank lines can be skipped:
+        //  root
+        //  \ta
+        //  \t\tb
+        //  \tc
+        //S-expression, a node with children is a list headed by its value,
+        // values with spaces, brackets or quotes are quoted:
+        //  (root (a b) c)
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct ParseError {
+            //Line for an outline, character offset for an s-expression.
+            pub at: usize,
+            pub message: String,
+        }
+
+        impl std::fmt::Display for ParseError {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "at {}: {}", self.at, self.message)
+            }
+        }
+
+        impl std::error::Error for ParseError {}
+
+        fn parse_error<R>(at: usize, message: &str) -> Result<R, ParseError> {
+            Err(ParseError {
+                at,
+                message: String::from(message),
+            })
+        }
+
+        //escape() writes a literal \e as \\e, so this can not be a value.
+        const EMPTY_VALUE: &str = "\\e";
+
+        impl<T: Display> Node<T> {
+            pub fn to_outline(self: &Rc<Self>) -> String {
+                let mut text = String::new();
+                let mut stack = vec![(Rc::clone(self), 0)];
+                while let Some((node, depth)) = stack.pop() {
+                    text.push_str(&"\t".repeat(depth));
+                    match node.value.to_string().as_str() {
+                        "" => text.push_str(EMPTY_VALUE),
+                        value => text.push_str(&escape(value)),
+                    }
+                    text.push('\n');
+                    for child in node.children.borrow().iter().rev() {
+                        stack.push((Rc::clone(child), depth + 1));
+                    }
+                }
+                text
+            }
+
+            pub fn to_sexpr(self: &Rc<Self>) -> String {
+                let mut text = String::new();
+                //None closes the list opened by the node before it.
+                let mut stack = vec![Some(Rc::clone(self))];
+                while let Some(next) = stack.pop() {
+                    let node = match next {
+                        Some(node) => node,
+                        None => {
+                            text.push(')');
+                            continue;
+                        }
+                    };
+                    if !text.is_empty() && !text.ends_with('(') {
+                        text.push(' ');
+                    }
+                    let children = node.children.borrow();
+                    if !children.is_empty() {
+                        text.push('(');
+                        stack.push(None);
+                        stack.extend(children.iter().rev().cloned().map(Some));
+                    }
+                    text.push_str(&quote(&node.value.to_string()));
+                }
+                text
+            }
+        }
+
+        impl<T: FromStr> Node<T> {
+            pub fn from_outline(text: &str) -> Result<Rc<Node<T>>, ParseError> {
+                let mut root = None;
+                //The last node seen at each depth.
+                let mut path: Vec<Rc<Node<T>>> = vec![];
+                for (n, line) in text.lines().enumerate() {
+                    let at = n + 1;
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let value = line.trim_start_matches('\t');
+                    let depth = line.len() - value.len();
+                    let value = match value {
+                        EMPTY_VALUE => Some(String::new()),
+                        value => unescape(value),
+                    };
+                    let node = match value.and_then(|v| v.parse().ok()) {
+                        Some(value) => Node::new(value),
+                        None => return parse_error(at, "bad value"),
+                    };
+                    if depth > path.len() || (depth == 0 && root.is_some()) {
+                        return parse_error(at, "bad indent");
+                    }
+                    path.truncate(depth);
+                    match path.last() {
+                        Some(parent) => parent.add_child(Rc::clone(&node)),
+                        None => root = Some(Rc::clone(&node)),
+                    }
+                    path.push(node);
+                }
+                match root {
+                    Some(root) => Ok(root),
+                    None => parse_error(0, "empty outline"),
+                }
+            }
+
+            pub fn from_sexpr(text: &str) -> Result<Rc<Node<T>>, ParseError> {
+                let mut tokens = tokens(text)?.into_iter();
+                let mut root = None;
+                //Lists still open, innermost last.
+                let mut open: Vec<Rc<Node<T>>> = vec![];
+                while let Some((at, token)) = tokens.next() {
+                    if root.is_some() && open.is_empty() {
+                        return parse_error(at, "text after the tree");
+                    }
+                    let (value_at, value, is_list) = match token {
+                        Token::Close => {
+                            if open.pop().is_none() {
+                                return parse_error(at, "unmatched )");
+                            }
+                            continue;
+                        }
+                        Token::Atom(value) => (at, value, false),
+                        Token::Open => match tokens.next() {
+                            Some((value_at, Token::Atom(value))) => (value_at, value, true),
+                            _ => return parse_error(at, "a list must start with a value"),
+                        },
+                    };
+                    let node = match value.parse() {
+                        Ok(value) => Node::new(value),
+                        Err(_) => return parse_error(value_at, "bad value"),
+                    };
+                    match open.last() {
+                        Some(parent) => parent.add_child(Rc::clone(&node)),
+                        None => root = Some(Rc::clone(&node)),
+                    }
+                    if is_list {
+                        open.push(node);
+                    }
+                }
+                if !open.is_empty() {
+                    return parse_error(text.chars().count(), "unclosed (");
+                }
+                match root {
+                    Some(root) => Ok(root),
+                    None => parse_error(0, "empty s-expression"),
+                }
+            }
+        }
+
+        enum Token {
+            Open,
+            Close,
+            Atom(String),
+        }
+
+        //Tokens with their character offsets.
+        fn tokens(text: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+            let mut tokens = vec![];
+            let mut chars = text.chars().enumerate().peekable();
+            while let Some((at, c)) = chars.next() {
+                match c {
+                    '(' => tokens.push((at, Token::Open)),
+                    ')' => tokens.push((at, Token::Close)),
+                    c if c.is_whitespace() => {}
+                    '"' => {
+                        let mut atom = String::new();
+                        loop {
+                            match chars.next() {
+                                Some((_, '"')) => break,
+                                Some((_, '\\')) => match chars.next() {
+                                    Some((_, 'n')) => atom.push('\n'),
+                                    Some((_, 't')) => atom.push('\t'),
+                                    Some((_, 'r')) => atom.push('\r'),
+                                    Some((_, c @ ('"' | '\\'))) => atom.push(c),
+                                    _ => return parse_error(at, "bad escape in quoted value"),
+                                },
+                                Some((_, c)) => atom.push(c),
+                                None => return parse_error(at, "unclosed quote"),
+                            }
+                        }
+                        tokens.push((at, Token::Atom(atom)));
+                    }
+                    c => {
+                        let mut atom = String::from(c);
+                        while let Some(&(_, c)) = chars.peek() {
+                            if c.is_whitespace() || "()\"".contains(c) {
+                                break;
+                            }
+                            atom.push(c);
+                            chars.next();
+                        }
+                        tokens.push((at, Token::Atom(atom)));
+                    }
+                }
+            }
+            Ok(tokens)
+        }
+
+        fn quote(value: &str) -> String {
+            let bare = !value.is_empty()
+                && !value
+                    .chars()
+                    .any(|c| c.is_whitespace() || "()\"\\".contains(c));
+            if bare {
+                return String::from(value);
+            }
+            let mut quoted = String::from("\"");
+            for c in value.chars() {
+                match c {
+                    '"' => quoted.push_str("\\\""),
+                    '\\' => quoted.push_str("\\\\"),
+                    '\n' => quoted.push_str("\\n"),
+                    '\t' => quoted.push_str("\\t"),
+                    '\r' => quoted.push_str("\\r"),
+                    c => quoted.push(c),
+                }
+            }
+            quoted.push('"');
+            quoted
+        }
+
+        fn counts<T>(node: &Rc<Node<T>>) -> (usize, usize) {
+            (Rc::strong_count(node), Rc::weak_count(node))
+        }
+
+        pub fn tree_example() {
+            let root = Node::new("root");
+            let a = Node::new("a");
+            let b = Node::new("b");
+            assert_eq!(counts(&root), (1, 0));
+
+            root.add_child(Rc::clone(&a));
+            //root.children holds a, a.parent is a Weak to root.
+            assert_eq!(counts(&root), (1, 1));
+            assert_eq!(counts(&a), (2, 0));
+            assert_eq!(a.parent().unwrap().value, "root");
+            assert_eq!(counts(&root), (1, 1));
+
+            a.add_child(Rc::clone(&b));
+            assert_eq!(counts(&a), (2, 1));
+            assert_eq!(counts(&b), (2, 0));
+            assert_eq!(b.depth(), 2);
+            let path: Vec<&str> = b.path_to_root().iter().map(|n| n.value).collect();
+            assert_eq!(path, vec!["b", "a", "root"]);
+            let ancestors: Vec<&str> = b.ancestors().map(|n| n.value).collect();
+            assert_eq!(ancestors, vec!["a", "root"]);
+            assert_eq!(counts(&root), (1, 1));
+            assert_eq!(counts(&a), (2, 1));
+
+            //Move b up to the root.
+            root.add_child(Rc::clone(&b));
+            assert_eq!(b.depth(), 1);
+            assert!(a.children().is_empty());
+            assert_eq!(counts(&root), (1, 2));
+            assert_eq!(counts(&a), (2, 0));
+            assert_eq!(counts(&b), (2, 0));
+
+            b.detach();
+            assert!(b.parent().is_none());
+            assert_eq!(b.depth(), 0);
+            assert_eq!(counts(&root), (1, 1));
+            assert_eq!(counts(&b), (1, 0));
+
+            //Only the Weak from a is left pointing at root.
+            let weak_root = Rc::downgrade(&root);
+            drop(root);
+            assert!(weak_root.upgrade().is_none());
+            assert!(a.parent().is_none());
+            assert_eq!(counts(&a), (1, 0));
+        }
+
+        pub fn traversal_example() {
+            //      1
+            //    2   3
+            //   4 5   6
+            let nodes: Vec<Rc<Node<i32>>> = (1..=6).map(Node::new).collect();
+            nodes[0].add_child(Rc::clone(&nodes[1]));
+            nodes[0].add_child(Rc::clone(&nodes[2]));
+            nodes[1].add_child(Rc::clone(&nodes[3]));
+            nodes[1].add_child(Rc::clone(&nodes[4]));
+            nodes[2].add_child(Rc::clone(&nodes[5]));
+
+            let dfs: Vec<i32> = nodes[0].depth_first().map(|n| n.value).collect();
+            assert_eq!(dfs, vec![1, 2, 4, 5, 3, 6]);
+            let bfs: Vec<i32> = nodes[0].breadth_first().map(|n| n.value).collect();
+            assert_eq!(bfs, vec![1, 2, 3, 4, 5, 6]);
+            let sub: Vec<i32> = nodes[1].depth_first().map(|n| n.value).collect();
+            assert_eq!(sub, vec![2, 4, 5]);
+
+            //Iterators let go of their clones, nothing leaks.
+            assert_eq!(counts(&nodes[0]), (1, 2));
+            for node in &nodes[1..] {
+                assert_eq!(Rc::strong_count(node), 2);
+            }
+
+            let weak: Vec<Weak<Node<i32>>> = nodes.iter().map(Rc::downgrade).collect();
+            drop(nodes);
+            assert!(weak.iter().all(|w| w.upgrade().is_none()));
+        }
+
+        pub fn cycle_example() {
+            let root = Node::new(1);
+            let child = Node::new(2);
+            root.add_child(Rc::clone(&child));
+            child.add_child(root);
+        }
+
+        //Every node has the same value and parent value as in other, and
+        // the parent links are real: each child is held once, each parent
+        // is pointed at by one Weak per child.
+        fn assert_same_tree(tree: &Rc<Node<String>>, other: &Rc<Node<String>>) {
+            let nodes: Vec<_> = tree.depth_first().collect();
+            let others: Vec<_> = other.depth_first().collect();
+            assert_eq!(nodes.len(), others.len());
+            for (node, other) in nodes.iter().zip(&others) {
+                assert_eq!(node.value, other.value);
+                assert_eq!(
+                    node.parent().map(|p| p.value.clone()),
+                    other.parent().map(|p| p.value.clone())
+                );
+                if let Some(parent) = node.parent() {
+                    assert!(parent.children().iter().any(|c| Rc::ptr_eq(c, node)));
+                }
+                assert_eq!(Rc::weak_count(node), node.children().len());
+            }
+            assert!(tree.parent().is_none());
+        }
+
+        fn sample_tree() -> Rc<Node<String>> {
+            let root = Node::new(String::from("root"));
+            let a = Node::new(String::from("a (with) \"brackets\""));
+            a.add_child(Node::new(String::from("tab\tand\nnewline")));
+            a.add_child(Node::new(String::from("  spaced")));
+            root.add_child(a);
+            root.add_child(Node::new(String::from("c\\d")));
+            root.add_child(Node::new(String::new()));
+            root
+        }
+
+        pub fn outline_example() {
+            let root: Rc<Node<String>> =
+                Node::from_outline("root\n\ta\n\t\tb\n\t\tc\n\td\n").unwrap();
+            let values: Vec<String> = root.depth_first().map(|n| n.value.clone()).collect();
+            assert_eq!(values, vec!["root", "a", "b", "c", "d"]);
+            let c = &root.children()[0].children()[1];
+            let path: Vec<String> = c.path_to_root().iter().map(|n| n.value.clone()).collect();
+            assert_eq!(path, vec!["c", "a", "root"]);
+            assert_eq!(root.to_outline(), "root\n\ta\n\t\tb\n\t\tc\n\td\n");
+
+            let tree = sample_tree();
+            let text = tree.to_outline();
+            assert_eq!(
+                text,
+                "root\n\ta (with) \"brackets\"\n\t\ttab\\tand\\nnewline\n\t\t  spaced\n\tc\\\\d\n\t\\e\n"
+            );
+            let back = Node::from_outline(&text).unwrap();
+            assert_same_tree(&back, &tree);
+            //Only the caller holds the root.
+            assert_eq!(Rc::strong_count(&back), 1);
+
+            //An empty root is written as \e and read back, a literal \e is
+            // still escaped as any other backslash.
+            let empty = Node::new(String::new());
+            assert_eq!(empty.to_outline(), "\\e\n");
+            assert_same_tree(&Node::from_outline(&empty.to_outline()).unwrap(), &empty);
+            empty.add_child(Node::new(String::from("\\e")));
+            assert_eq!(empty.to_outline(), "\\e\n\t\\\\e\n");
+            assert_same_tree(&Node::from_outline(&empty.to_outline()).unwrap(), &empty);
+
+            //Blank lines, such as a trailing one added by an editor, are skipped.
+            let blank: Rc<Node<i32>> = Node::from_outline("1\n\n\t2\n\n").unwrap();
+            assert_eq!(blank.to_outline(), "1\n\t2\n");
+
+            let numbers: Rc<Node<i32>> = Node::from_outline("1\n\t2\n\t3").unwrap();
+            assert_eq!(numbers.children()[1].value, 3);
+
+            let bad = |text: &str| Node::<i32>::from_outline(text).unwrap_err().at;
+            assert_eq!(bad(""), 0);
+            assert_eq!(bad("1\n\t\t2\n"), 2);
+            assert_eq!(bad("1\n2\n"), 2);
+            assert_eq!(bad("1\n\tx\n"), 2);
+        }
+
+        pub fn sexpr_example() {
+            let root: Rc<Node<String>> = Node::from_sexpr("(root (a b c) d)").unwrap();
+            let values: Vec<String> = root.depth_first().map(|n| n.value.clone()).collect();
+            assert_eq!(values, vec!["root", "a", "b", "c", "d"]);
+            assert_eq!(root.children()[0].children()[1].depth(), 2);
+            assert_eq!(root.to_sexpr(), "(root (a b c) d)");
+
+            let tree = sample_tree();
+            let text = tree.to_sexpr();
+            assert_eq!(
+                text,
+                "(root (\"a (with) \\\"brackets\\\"\" \"tab\\tand\\nnewline\" \"  spaced\") \"c\\\\d\" \"\")"
+            );
+            let back = Node::from_sexpr(&text).unwrap();
+            assert_same_tree(&back, &tree);
+            assert_same_tree(&Node::from_outline(&back.to_outline()).unwrap(), &tree);
+
+            let leaf: Rc<Node<i32>> = Node::from_sexpr(" 7 ").unwrap();
+            assert_eq!(leaf.value, 7);
+            assert!(leaf.children().is_empty());
+
+            let bad = |text: &str| Node::<i32>::from_sexpr(text).unwrap_err().at;
+            assert_eq!(bad(""), 0);
+            assert_eq!(bad("(1 2"), 4);
+            assert_eq!(bad("(1 2))"), 5);
+            assert_eq!(bad("(1 x)"), 3);
+            assert_eq!(bad("(1) 2"), 4);
+            assert_eq!(bad("(() 1)"), 0);
+            assert_eq!(bad("(1 \"2)"), 3);
         }
 
         pub fn weak_ex() {
             let leaf = Rc::new(Node {
-                _value: 3,
+                value: 3,
                 parent: RefCell::new(Weak::new()),
-                _children: RefCell::new(vec![]),
+                children: RefCell::new(vec![]),
             });
             println!(
                 "leaf strong = {}, weak = {}",
@@ -255,9 +831,9 @@ pub mod tests {
                 println!("leaf parent = {:?}", leaf.parent.borrow().upgrade());
 
                 let branch = Rc::new(Node {
-                    _value: 5,
+                    value: 5,
                     parent: RefCell::new(Weak::new()),
-                    _children: RefCell::new(vec![Rc::clone(&leaf)]),
+                    children: RefCell::new(vec![Rc::clone(&leaf)]),
                 });
 
                 *leaf.parent.borrow_mut() = Rc::downgrade(&branch);
@@ -281,138 +857,3830 @@ pub mod tests {
             );
         }
     }
-    //-Rc<T>, RefCell<T> Example---------------------------------------------/
-    // Multiple owers of mutable data Rc<T> with RefCell<T>
-    //Cell<T> does not give references but copies the value in and out.
-    //Mutex<T> gives interior Mutability across threads.
+    //-Event Bus Example------------------------------------------------------/
+    //Subscribers are held by Weak, the bus never keeps one alive. Dropping
+    // the last Rc unsubscribes and the dead entry is pruned on publish.
     #[test]
-    pub fn rc_refcell_run() {
-        rcref::rc_refcell_example();
+    pub fn event_bus_publish() {
+        event_bus::publish_example();
     }
 
-    mod rcref {
+    #[test]
+    pub fn event_bus_sync() {
+        event_bus::sync_example();
+    }
 
-        #[derive(Debug)]
-        enum List {
-            Cons(Rc<RefCell<i32>>, Rc<List>),
-            Nil,
+    pub mod event_bus {
+        use std::cell::RefCell;
+        use std::rc::{Rc, Weak};
+        use std::sync::{Arc, Mutex};
+
+        pub trait Subscriber<E> {
+            fn notify(&self, event: &E);
         }
 
-        use crate::tests::rcref::List::{Cons, Nil};
-        use std::cell::RefCell;
-        use std::rc::Rc;
+        //Closures subscribe directly, eg. Rc::new(|e: &Event| ...).
+        impl<E, F: Fn(&E)> Subscriber<E> for F {
+            fn notify(&self, event: &E) {
+                self(event)
+            }
+        }
 
-        pub fn rc_refcell_example() {
-            let value = Rc::new(RefCell::new(5));
+        pub struct EventBus<E> {
+            subscribers: RefCell<Vec<Weak<dyn Subscriber<E>>>>,
+        }
 
-            let a = Rc::new(Cons(Rc::clone(&value), Rc::new(Nil)));
+        impl<E> Default for EventBus<E> {
+            fn default() -> Self {
+                EventBus {
+                    subscribers: RefCell::new(vec![]),
+                }
+            }
+        }
 
-            let b = Cons(Rc::new(RefCell::new(3)), Rc::clone(&a));
-            let c = Cons(Rc::new(RefCell::new(4)), Rc::clone(&a));
+        impl<E> EventBus<E> {
+            pub fn new() -> EventBus<E> {
+                EventBus::default()
+            }
 
-            *value.borrow_mut() += 10;
+            pub fn subscribe<S: Subscriber<E> + 'static>(&self, subscriber: &Rc<S>) {
+                let subscriber: Rc<dyn Subscriber<E>> = subscriber.clone();
+                self.subscribers
+                    .borrow_mut()
+                    .push(Rc::downgrade(&subscriber));
+            }
 
-            println!("a after = {:?}", a);
-            println!("b after = {:?}", b);
-            println!("c after = {:?}", c);
+            //Registered entries, including dropped ones not yet pruned.
+            pub fn subscriber_count(&self) -> usize {
+                self.subscribers.borrow().len()
+            }
+
+            //Prune dropped subscribers then notify the rest, returning how
+            // many were notified. The list is not borrowed during notify so
+            // a subscriber may subscribe or publish again.
+            pub fn publish(&self, event: &E) -> usize {
+                let live: Vec<Rc<dyn Subscriber<E>>> = {
+                    let mut subscribers = self.subscribers.borrow_mut();
+                    subscribers.retain(|s| s.strong_count() > 0);
+                    subscribers.iter().filter_map(Weak::upgrade).collect()
+                };
+                for subscriber in &live {
+                    subscriber.notify(event);
+                }
+                live.len()
+            }
         }
-    }
 
-    // RefCell<T> allows mutation of immutable references.
-    // Gives single ownership over it's data.
+        type SyncSubscriber<E> = dyn Subscriber<E> + Send + Sync;
 
-    //Borrow Rules
-    // - At any given time, you can have either (but not both of)
-    //   one mutable reference or any number of immutable references.
-    // - References must always be valid.
+        //Thread safe variant holding std::sync::Weak handles.
+        pub struct SyncEventBus<E> {
+            subscribers: Mutex<Vec<std::sync::Weak<SyncSubscriber<E>>>>,
+        }
 
-    //With references and Box<T>, the borrowing rules’ invariants are enforced
-    //at compile time. With RefCell<T>, these invariants are enforced at runtime.
-    //With references, if you break these rules, you’ll get a compiler error.
-    //With RefCell<T>, if you break these rules, your program will panic and exit.
+        impl<E> Default for SyncEventBus<E> {
+            fn default() -> Self {
+                SyncEventBus {
+                    subscribers: Mutex::new(vec![]),
+                }
+            }
+        }
 
-    // - Rc<T> enables multiple owners of the same data;
-    // - Box<T> and RefCell<T> have single owners.
+        impl<E> SyncEventBus<E> {
+            pub fn new() -> SyncEventBus<E> {
+                SyncEventBus::default()
+            }
 
-    // - Box<T> allows immutable or mutable borrows checked at compile time;
-    // - Rc<T> allows only immutable borrows checked at compile time;
-    // - RefCell<T> allows immutable or mutable borrows checked at runtime.
+            pub fn subscribe<S>(&self, subscriber: &Arc<S>)
+            where
+                S: Subscriber<E> + Send + Sync + 'static,
+            {
+                let subscriber: Arc<SyncSubscriber<E>> = subscriber.clone();
+                self.subscribers
+                    .lock()
+                    .unwrap()
+                    .push(Arc::downgrade(&subscriber));
+            }
 
-    // RefCell<T> allows mutable borrows checked at runtime, allowing mutation
-    // of the value inside the RefCell<T> even when the RefCell<T> is immutable.
+            pub fn subscriber_count(&self) -> usize {
+                self.subscribers.lock().unwrap().len()
+            }
 
-    // & - create an immutable reference.   &mut - create a mutable reference.
-    // RefCell.borrow() for immutable reference.
-    // RefCell.borrow_mut() for mutable reference.
-    // Allows many immutable refs but only one mutable.
+            //As EventBus::publish, the lock is released before notifying.
+            pub fn publish(&self, event: &E) -> usize {
+                let live: Vec<Arc<SyncSubscriber<E>>> = {
+                    let mut subscribers = self.subscribers.lock().unwrap();
+                    subscribers.retain(|s| s.strong_count() > 0);
+                    subscribers
+                        .iter()
+                        .filter_map(std::sync::Weak::upgrade)
+                        .collect()
+                };
+                for subscriber in &live {
+                    subscriber.notify(event);
+                }
+                live.len()
+            }
+        }
+
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum Event {
+            Opened(String),
+            Closed,
+        }
+
+        struct Recorder {
+            seen: RefCell<Vec<Event>>,
+        }
+
+        impl Subscriber<Event> for Recorder {
+            fn notify(&self, event: &Event) {
+                self.seen.borrow_mut().push(event.clone());
+            }
+        }
+
+        pub fn publish_example() {
+            let bus = EventBus::new();
+            let recorder = Rc::new(Recorder {
+                seen: RefCell::new(vec![]),
+            });
+            let closed = Rc::new(RefCell::new(0));
+            let counter = {
+                let closed = Rc::clone(&closed);
+                Rc::new(move |event: &Event| {
+                    if *event == Event::Closed {
+                        *closed.borrow_mut() += 1;
+                    }
+                })
+            };
+            bus.subscribe(&recorder);
+            bus.subscribe(&counter);
+            //The bus only holds weak references.
+            assert_eq!(Rc::strong_count(&recorder), 1);
+            assert_eq!(Rc::weak_count(&recorder), 1);
+
+            assert_eq!(bus.publish(&Event::Opened("a".to_string())), 2);
+            assert_eq!(bus.publish(&Event::Closed), 2);
+
+            drop(counter);
+            assert_eq!(bus.subscriber_count(), 2);
+            assert_eq!(bus.publish(&Event::Closed), 1);
+            assert_eq!(bus.subscriber_count(), 1);
+            assert_eq!(*closed.borrow(), 1);
+            assert_eq!(
+                *recorder.seen.borrow(),
+                vec![Event::Opened("a".to_string()), Event::Closed, Event::Closed]
+            );
+
+            //A subscriber may subscribe others while being notified.
+            let bus = Rc::new(bus);
+            let late = Rc::new(Recorder {
+                seen: RefCell::new(vec![]),
+            });
+            let joiner = {
+                let bus = Rc::downgrade(&bus);
+                let late = Rc::clone(&late);
+                Rc::new(move |_: &Event| {
+                    if let Some(bus) = bus.upgrade() {
+                        bus.subscribe(&late);
+                    }
+                })
+            };
+            bus.subscribe(&joiner);
+            assert_eq!(bus.publish(&Event::Closed), 2);
+            drop(joiner);
+            drop(recorder);
+            assert_eq!(bus.publish(&Event::Opened("b".to_string())), 1);
+            assert_eq!(*late.seen.borrow(), vec![Event::Opened("b".to_string())]);
+        }
+
+        pub fn sync_example() {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            use std::thread;
+
+            let bus: SyncEventBus<usize> = SyncEventBus::new();
+            let total = Arc::new(AtomicUsize::new(0));
+            let adder = {
+                let total = Arc::clone(&total);
+                Arc::new(move |n: &usize| {
+                    total.fetch_add(*n, Ordering::SeqCst);
+                })
+            };
+            let calls = Arc::new(AtomicUsize::new(0));
+            let counter = {
+                let calls = Arc::clone(&calls);
+                Arc::new(move |_: &usize| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                })
+            };
+            bus.subscribe(&adder);
+            bus.subscribe(&counter);
+
+            thread::scope(|scope| {
+                for n in 1..=4 {
+                    let bus = &bus;
+                    scope.spawn(move || assert_eq!(bus.publish(&n), 2));
+                }
+            });
+            assert_eq!(total.load(Ordering::SeqCst), 10);
+            assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+            drop(adder);
+            thread::spawn({
+                let counter = Arc::clone(&counter);
+                move || drop(counter)
+            })
+            .join()
+            .unwrap();
+            assert_eq!(bus.publish(&100), 1);
+            assert_eq!(bus.subscriber_count(), 1);
+            assert_eq!(total.load(Ordering::SeqCst), 10);
+            assert_eq!(calls.load(Ordering::SeqCst), 5);
+        }
+    }
+
+    //-Arena Tree Example-----------------------------------------------------/
+    //The weak::Node tree as indexes into a Vec. No Rc, RefCell or Weak, a
+    // NodeId is a slot index plus a generation so an id kept after its node
+    // was removed is caught instead of reading whatever reused the slot.
     #[test]
-    pub fn refcell_example() {
-        let mock_messenger = MockMessenger::new();
-        let mut limit_tracker = LimitTracker::new(&mock_messenger, 100);
-        limit_tracker.set_value(80);
-        assert_eq!(mock_messenger.sent_messages.borrow_mut().len(), 1);
+    pub fn arena_tree() {
+        arena::tree_example();
+    }
+
+    #[test]
+    pub fn arena_tree_traversal() {
+        arena::traversal_example();
+    }
+
+    #[test]
+    #[should_panic(expected = "own ancestor")]
+    pub fn arena_tree_no_cycles() {
+        arena::cycle_example();
+    }
+
+    //cargo test --release tree_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    pub fn tree_benchmark() {
+        arena::benchmark(1_000_000);
     }
 
-    pub trait Messenger {
-        fn send(&self, msg: &str);
-    }
+    pub mod arena {
+        use super::weak;
+        use std::collections::VecDeque;
+        use std::hint::black_box;
+        use std::rc::Rc;
+        use std::time::Instant;
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct NodeId {
+            index: usize,
+            generation: u32,
+        }
+
+        struct Slot<T> {
+            generation: u32,
+            node: Option<ArenaNode<T>>,
+        }
+
+        struct ArenaNode<T> {
+            value: T,
+            parent: Option<NodeId>,
+            children: Vec<NodeId>,
+        }
+
+        pub struct Arena<T> {
+            slots: Vec<Slot<T>>,
+            free: Vec<usize>,
+        }
+
+        impl<T> Default for Arena<T> {
+            fn default() -> Self {
+                Arena {
+                    slots: vec![],
+                    free: vec![],
+                }
+            }
+        }
+
+        impl<T> Arena<T> {
+            pub fn new() -> Arena<T> {
+                Arena::default()
+            }
+
+            //A node without a parent.
+            pub fn new_node(&mut self, value: T) -> NodeId {
+                let node = Some(ArenaNode {
+                    value,
+                    parent: None,
+                    children: vec![],
+                });
+                match self.free.pop() {
+                    Some(index) => {
+                        let slot = &mut self.slots[index];
+                        slot.node = node;
+                        NodeId {
+                            index,
+                            generation: slot.generation,
+                        }
+                    }
+                    None => {
+                        self.slots.push(Slot {
+                            generation: 0,
+                            node,
+                        });
+                        NodeId {
+                            index: self.slots.len() - 1,
+                            generation: 0,
+                        }
+                    }
+                }
+            }
+
+            pub fn len(&self) -> usize {
+                self.slots.len() - self.free.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            pub fn contains(&self, id: NodeId) -> bool {
+                self.slot(id).is_some()
+            }
+
+            fn slot(&self, id: NodeId) -> Option<&ArenaNode<T>> {
+                let slot = self.slots.get(id.index)?;
+                if slot.generation != id.generation {
+                    return None;
+                }
+                slot.node.as_ref()
+            }
+
+            //Panics on a removed node, like indexing a Vec out of range.
+            fn node(&self, id: NodeId) -> &ArenaNode<T> {
+                self.slot(id).expect("NodeId of a removed node")
+            }
+
+            fn node_mut(&mut self, id: NodeId) -> &mut ArenaNode<T> {
+                match self.slots.get_mut(id.index) {
+                    Some(slot) if slot.generation == id.generation => slot.node.as_mut(),
+                    _ => None,
+                }
+                .expect("NodeId of a removed node")
+            }
+
+            pub fn value(&self, id: NodeId) -> &T {
+                &self.node(id).value
+            }
+
+            pub fn value_mut(&mut self, id: NodeId) -> &mut T {
+                &mut self.node_mut(id).value
+            }
+
+            //Moves child here if it already has a parent.
+            //Panics if child is parent or one of its ancestors.
+            pub fn add_child(&mut self, parent: NodeId, child: NodeId) {
+                assert!(
+                    parent != child && !self.ancestors(parent).any(|n| n == child),
+                    "can not add a node as a child of itself or its own ancestor"
+                );
+                self.detach(child);
+                self.node_mut(child).parent = Some(parent);
+                self.node_mut(parent).children.push(child);
+            }
+
+            //Remove from the parent, the node keeps its own children.
+            pub fn detach(&mut self, id: NodeId) {
+                if let Some(parent) = self.node_mut(id).parent.take() {
+                    self.node_mut(parent).children.retain(|&c| c != id);
+                }
+            }
+
+            //Free id and everything below it, returns the number of nodes freed.
+            pub fn remove(&mut self, id: NodeId) -> usize {
+                self.detach(id);
+                let ids: Vec<NodeId> = self.depth_first(id).collect();
+                for id in &ids {
+                    let slot = &mut self.slots[id.index];
+                    slot.node = None;
+                    slot.generation = slot.generation.wrapping_add(1);
+                    self.free.push(id.index);
+                }
+                ids.len()
+            }
+
+            pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+                self.node(id).parent
+            }
+
+            pub fn children(&self, id: NodeId) -> &[NodeId] {
+                &self.node(id).children
+            }
+
+            //Parent first, root last.
+            pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+                std::iter::successors(self.parent(id), move |&id| self.parent(id))
+            }
+
+            pub fn depth(&self, id: NodeId) -> usize {
+                self.ancestors(id).count()
+            }
+
+            //id first, root last.
+            pub fn path_to_root(&self, id: NodeId) -> Vec<NodeId> {
+                let mut path = vec![id];
+                path.extend(self.ancestors(id));
+                path
+            }
+
+            //Pre-order, id first.
+            pub fn depth_first(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+                let mut stack = vec![id];
+                std::iter::from_fn(move || {
+                    let id = stack.pop()?;
+                    stack.extend(self.children(id).iter().rev());
+                    Some(id)
+                })
+            }
+
+            //Level by level, id first.
+            pub fn breadth_first(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+                let mut queue = VecDeque::from([id]);
+                std::iter::from_fn(move || {
+                    let id = queue.pop_front()?;
+                    queue.extend(self.children(id));
+                    Some(id)
+                })
+            }
+        }
+
+        pub fn tree_example() {
+            let mut arena = Arena::new();
+            let root = arena.new_node("root");
+            let a = arena.new_node("a");
+            let b = arena.new_node("b");
+
+            arena.add_child(root, a);
+            arena.add_child(a, b);
+            assert_eq!(arena.parent(a), Some(root));
+            assert_eq!(arena.depth(b), 2);
+            let path: Vec<&str> = arena
+                .path_to_root(b)
+                .iter()
+                .map(|&n| *arena.value(n))
+                .collect();
+            assert_eq!(path, vec!["b", "a", "root"]);
+            let ancestors: Vec<NodeId> = arena.ancestors(b).collect();
+            assert_eq!(ancestors, vec![a, root]);
+
+            //Move b up to the root.
+            arena.add_child(root, b);
+            assert_eq!(arena.depth(b), 1);
+            assert!(arena.children(a).is_empty());
+            assert_eq!(arena.children(root), &[a, b]);
+
+            arena.detach(b);
+            assert_eq!(arena.parent(b), None);
+            assert_eq!(arena.children(root), &[a]);
+
+            *arena.value_mut(b) = "b2";
+            arena.add_child(a, b);
+            assert_eq!(arena.remove(a), 2);
+            assert_eq!(arena.len(), 1);
+            assert!(arena.children(root).is_empty());
+
+            //The freed slot is reused, the old id does not see the new node.
+            let c = arena.new_node("c");
+            assert_eq!(arena.len(), 2);
+            assert!(!arena.contains(a) && !arena.contains(b));
+            assert!(arena.contains(c));
+            assert_eq!(arena.value(c), &"c");
+        }
+
+        pub fn traversal_example() {
+            //      1
+            //    2   3
+            //   4 5   6
+            let mut arena = Arena::new();
+            let nodes: Vec<NodeId> = (1..=6).map(|v| arena.new_node(v)).collect();
+            arena.add_child(nodes[0], nodes[1]);
+            arena.add_child(nodes[0], nodes[2]);
+            arena.add_child(nodes[1], nodes[3]);
+            arena.add_child(nodes[1], nodes[4]);
+            arena.add_child(nodes[2], nodes[5]);
+
+            let dfs: Vec<i32> = arena
+                .depth_first(nodes[0])
+                .map(|n| *arena.value(n))
+                .collect();
+            assert_eq!(dfs, vec![1, 2, 4, 5, 3, 6]);
+            let bfs: Vec<i32> = arena
+                .breadth_first(nodes[0])
+                .map(|n| *arena.value(n))
+                .collect();
+            assert_eq!(bfs, vec![1, 2, 3, 4, 5, 6]);
+            let sub: Vec<i32> = arena
+                .depth_first(nodes[1])
+                .map(|n| *arena.value(n))
+                .collect();
+            assert_eq!(sub, vec![2, 4, 5]);
+            assert!(!arena.is_empty());
+        }
+
+        pub fn cycle_example() {
+            let mut arena = Arena::new();
+            let root = arena.new_node(1);
+            let child = arena.new_node(2);
+            arena.add_child(root, child);
+            arena.add_child(child, root);
+        }
+
+        //Build a tree with fan out 8, walk it both ways and drop it, for
+        // the Rc/Weak tree and the arena tree.
+        pub fn benchmark(size: usize) {
+            let time = |name: &str, start: Instant| {
+                println!("{:>8} {:<22} {:?}", size, name, start.elapsed());
+            };
+
+            let start = Instant::now();
+            let nodes: Vec<Rc<weak::Node<usize>>> = (0..size).map(weak::Node::new).collect();
+            for i in 1..size {
+                nodes[(i - 1) / 8].add_child(Rc::clone(&nodes[i]));
+            }
+            let root = Rc::clone(&nodes[0]);
+            drop(nodes);
+            time("rc build", start);
+
+            let start = Instant::now();
+            let sum: usize = root.depth_first().map(|n| n.value).sum();
+            black_box(sum);
+            time("rc depth first", start);
+
+            let start = Instant::now();
+            let sum: usize = root.breadth_first().map(|n| n.value).sum();
+            black_box(sum);
+            time("rc breadth first", start);
+
+            let start = Instant::now();
+            drop(root);
+            time("rc drop", start);
+
+            let start = Instant::now();
+            let mut arena = Arena::new();
+            let nodes: Vec<NodeId> = (0..size).map(|v| arena.new_node(v)).collect();
+            for i in 1..size {
+                arena.add_child(nodes[(i - 1) / 8], nodes[i]);
+            }
+            let root = nodes[0];
+            drop(nodes);
+            time("arena build", start);
+
+            let start = Instant::now();
+            let sum: usize = arena.depth_first(root).map(|n| *arena.value(n)).sum();
+            black_box(sum);
+            time("arena depth first", start);
+
+            let start = Instant::now();
+            let sum: usize = arena.breadth_first(root).map(|n| *arena.value(n)).sum();
+            black_box(sum);
+            time("arena breadth first", start);
+
+            let start = Instant::now();
+            drop(arena);
+            time("arena drop", start);
+        }
+    }
+
+    //-Doubly Linked List Example---------------------------------------------/
+    //Each node owns the next with Rc<RefCell<_>> and points back with Weak,
+    // so the list has no reference cycle and frees itself when dropped.
+    #[test]
+    pub fn dlist_ends() {
+        dlist::ends_example();
+    }
+
+    #[test]
+    pub fn dlist_cursor() {
+        dlist::cursor_example();
+    }
+
+    #[test]
+    pub fn dlist_no_leaks() {
+        dlist::no_leak_example();
+    }
+
+    pub mod dlist {
+        use std::cell::{Cell, Ref, RefCell, RefMut};
+        use std::fmt;
+        use std::marker::PhantomData;
+        use std::rc::{Rc, Weak};
+
+        type NodeRef<T> = Rc<RefCell<Node<T>>>;
+
+        struct Node<T> {
+            value: T,
+            next: Option<NodeRef<T>>,
+            prev: Weak<RefCell<Node<T>>>,
+        }
+
+        impl<T> Node<T> {
+            fn new(value: T, prev: Weak<RefCell<Node<T>>>, next: Option<NodeRef<T>>) -> NodeRef<T> {
+                Rc::new(RefCell::new(Node { value, next, prev }))
+            }
+        }
+
+        //A node taken out of the list has no other owner.
+        fn into_value<T>(node: NodeRef<T>) -> T {
+            match Rc::try_unwrap(node) {
+                Ok(node) => node.into_inner().value,
+                Err(_) => unreachable!("unlinked node is still shared"),
+            }
+        }
+
+        pub struct DList<T> {
+            head: Option<NodeRef<T>>,
+            tail: Weak<RefCell<Node<T>>>,
+            len: usize,
+        }
+
+        impl<T> Default for DList<T> {
+            fn default() -> Self {
+                DList {
+                    head: None,
+                    tail: Weak::new(),
+                    len: 0,
+                }
+            }
+        }
+
+        impl<T> DList<T> {
+            pub fn new() -> DList<T> {
+                DList::default()
+            }
+
+            pub fn len(&self) -> usize {
+                self.len
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.len == 0
+            }
+
+            pub fn push_front(&mut self, value: T) {
+                let node = Node::new(value, Weak::new(), self.head.take());
+                match &node.borrow().next {
+                    Some(old) => old.borrow_mut().prev = Rc::downgrade(&node),
+                    None => self.tail = Rc::downgrade(&node),
+                }
+                self.head = Some(node);
+                self.len += 1;
+            }
+
+            pub fn push_back(&mut self, value: T) {
+                let node = Node::new(value, self.tail.clone(), None);
+                let tail = Rc::downgrade(&node);
+                match self.tail.upgrade() {
+                    Some(old) => old.borrow_mut().next = Some(node),
+                    None => self.head = Some(node),
+                }
+                self.tail = tail;
+                self.len += 1;
+            }
+
+            pub fn pop_front(&mut self) -> Option<T> {
+                let old = self.head.take()?;
+                match old.borrow_mut().next.take() {
+                    Some(next) => {
+                        next.borrow_mut().prev = Weak::new();
+                        self.head = Some(next);
+                    }
+                    None => self.tail = Weak::new(),
+                }
+                self.len -= 1;
+                Some(into_value(old))
+            }
+
+            pub fn pop_back(&mut self) -> Option<T> {
+                let old = self.tail.upgrade()?;
+                let prev = std::mem::take(&mut old.borrow_mut().prev);
+                match prev.upgrade() {
+                    Some(prev) => {
+                        prev.borrow_mut().next = None;
+                        self.tail = Rc::downgrade(&prev);
+                    }
+                    None => {
+                        self.head = None;
+                        self.tail = Weak::new();
+                    }
+                }
+                self.len -= 1;
+                Some(into_value(old))
+            }
+
+            pub fn peek_front(&self) -> Option<Ref<'_, T>> {
+                let head = self.head.as_ref()?;
+                Some(Ref::map(head.borrow(), |node| &node.value))
+            }
+
+            pub fn peek_front_mut(&mut self) -> Option<RefMut<'_, T>> {
+                let head = self.head.as_ref()?;
+                Some(RefMut::map(head.borrow_mut(), |node| &mut node.value))
+            }
+
+            //The tail is only held by a Weak, so the value comes back
+            // through its own Rc rather than a Ref.
+            pub fn peek_back(&self) -> Option<T>
+            where
+                T: Clone,
+            {
+                let tail = self.tail.upgrade()?;
+                let value = tail.borrow().value.clone();
+                Some(value)
+            }
+
+            //Clones each value, the RefCell borrows can not outlive a
+            // step of the iterator.
+            pub fn iter(&self) -> Iter<'_, T>
+            where
+                T: Clone,
+            {
+                Iter {
+                    front: self.head.clone(),
+                    back: self.tail.upgrade(),
+                    len: self.len,
+                    list: PhantomData,
+                }
+            }
+
+            //At the head, or the ghost position if the list is empty.
+            pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+                CursorMut {
+                    current: self.head.clone(),
+                    index: 0,
+                    list: self,
+                }
+            }
+
+            //At the tail, or the ghost position if the list is empty.
+            pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T> {
+                CursorMut {
+                    current: self.tail.upgrade(),
+                    index: self.len.saturating_sub(1),
+                    list: self,
+                }
+            }
+        }
+
+        //Unlink front to back so dropping a long list does not recurse.
+        impl<T> Drop for DList<T> {
+            fn drop(&mut self) {
+                let mut next = self.head.take();
+                while let Some(node) = next {
+                    next = node.borrow_mut().next.take();
+                }
+            }
+        }
+
+        impl<T> FromIterator<T> for DList<T> {
+            fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+                let mut list = DList::new();
+                for value in iter {
+                    list.push_back(value);
+                }
+                list
+            }
+        }
+
+        impl<T: fmt::Debug> fmt::Debug for DList<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let mut list = f.debug_list();
+                let mut next = self.head.clone();
+                while let Some(node) = next {
+                    list.entry(&node.borrow().value);
+                    next = node.borrow().next.clone();
+                }
+                list.finish()
+            }
+        }
+
+        pub struct Iter<'a, T> {
+            front: Option<NodeRef<T>>,
+            back: Option<NodeRef<T>>,
+            len: usize,
+            list: PhantomData<&'a DList<T>>,
+        }
+
+        //Holding node clones past the borrow would let a pop find its node
+        // still shared, a Drop impl keeps the list borrowed until here.
+        impl<T> Drop for Iter<'_, T> {
+            fn drop(&mut self) {
+                self.front = None;
+                self.back = None;
+            }
+        }
+
+        impl<T: Clone> Iterator for Iter<'_, T> {
+            type Item = T;
+
+            fn next(&mut self) -> Option<T> {
+                if self.len == 0 {
+                    return None;
+                }
+                let node = self.front.take()?;
+                self.front = node.borrow().next.clone();
+                self.len -= 1;
+                let value = node.borrow().value.clone();
+                Some(value)
+            }
+        }
+
+        impl<T: Clone> DoubleEndedIterator for Iter<'_, T> {
+            fn next_back(&mut self) -> Option<T> {
+                if self.len == 0 {
+                    return None;
+                }
+                let node = self.back.take()?;
+                self.back = node.borrow().prev.upgrade();
+                self.len -= 1;
+                let value = node.borrow().value.clone();
+                Some(value)
+            }
+        }
+
+        pub struct IntoIter<T>(DList<T>);
+
+        impl<T> Iterator for IntoIter<T> {
+            type Item = T;
+
+            fn next(&mut self) -> Option<T> {
+                self.0.pop_front()
+            }
+        }
+
+        impl<T> DoubleEndedIterator for IntoIter<T> {
+            fn next_back(&mut self) -> Option<T> {
+                self.0.pop_back()
+            }
+        }
+
+        impl<T> IntoIterator for DList<T> {
+            type Item = T;
+            type IntoIter = IntoIter<T>;
+
+            fn into_iter(self) -> IntoIter<T> {
+                IntoIter(self)
+            }
+        }
+
+        //Points at a node, or at the ghost position between the tail and
+        // the head where current() is None, like std LinkedList cursors.
+        pub struct CursorMut<'a, T> {
+            list: &'a mut DList<T>,
+            current: Option<NodeRef<T>>,
+            //list.len() at the ghost position.
+            index: usize,
+        }
+
+        impl<T> CursorMut<'_, T> {
+            pub fn index(&self) -> Option<usize> {
+                self.current.as_ref().map(|_| self.index)
+            }
+
+            pub fn current(&self) -> Option<Ref<'_, T>> {
+                let node = self.current.as_ref()?;
+                Some(Ref::map(node.borrow(), |node| &node.value))
+            }
+
+            pub fn current_mut(&mut self) -> Option<RefMut<'_, T>> {
+                let node = self.current.as_ref()?;
+                Some(RefMut::map(node.borrow_mut(), |node| &mut node.value))
+            }
+
+            pub fn move_next(&mut self) {
+                match self.current.take() {
+                    Some(node) => {
+                        self.current = node.borrow().next.clone();
+                        self.index += 1;
+                    }
+                    None => {
+                        self.current = self.list.head.clone();
+                        self.index = 0;
+                    }
+                }
+            }
+
+            pub fn move_prev(&mut self) {
+                match self.current.take() {
+                    Some(node) => {
+                        self.current = node.borrow().prev.upgrade();
+                        self.index = match self.current {
+                            Some(_) => self.index - 1,
+                            None => self.list.len,
+                        };
+                    }
+                    None => {
+                        self.current = self.list.tail.upgrade();
+                        self.index = self.list.len.saturating_sub(1);
+                    }
+                }
+            }
+
+            //At the ghost position this pushes to the back.
+            pub fn insert_before(&mut self, value: T) {
+                let current = match &self.current {
+                    Some(current) => Rc::clone(current),
+                    None => {
+                        self.list.push_back(value);
+                        self.index = self.list.len;
+                        return;
+                    }
+                };
+                let prev = current.borrow().prev.clone();
+                match prev.upgrade() {
+                    Some(prev_node) => {
+                        let node = Node::new(value, prev, Some(Rc::clone(&current)));
+                        current.borrow_mut().prev = Rc::downgrade(&node);
+                        prev_node.borrow_mut().next = Some(node);
+                        self.list.len += 1;
+                    }
+                    None => self.list.push_front(value),
+                }
+                self.index += 1;
+            }
+
+            //At the ghost position this pushes to the front.
+            pub fn insert_after(&mut self, value: T) {
+                let current = match &self.current {
+                    Some(current) => Rc::clone(current),
+                    None => {
+                        self.list.push_front(value);
+                        self.index = self.list.len;
+                        return;
+                    }
+                };
+                let next = current.borrow_mut().next.take();
+                match next {
+                    Some(next) => {
+                        let node =
+                            Node::new(value, Rc::downgrade(&current), Some(Rc::clone(&next)));
+                        next.borrow_mut().prev = Rc::downgrade(&node);
+                        current.borrow_mut().next = Some(node);
+                        self.list.len += 1;
+                    }
+                    None => self.list.push_back(value),
+                }
+            }
+
+            //Remove the current value, the cursor moves on to the next.
+            pub fn remove_current(&mut self) -> Option<T> {
+                let node = self.current.take()?;
+                let prev = std::mem::take(&mut node.borrow_mut().prev);
+                let next = node.borrow_mut().next.take();
+                match prev.upgrade() {
+                    Some(prev_node) => prev_node.borrow_mut().next = next.clone(),
+                    None => self.list.head = next.clone(),
+                }
+                match &next {
+                    Some(next_node) => next_node.borrow_mut().prev = prev,
+                    None => self.list.tail = prev,
+                }
+                self.list.len -= 1;
+                if next.is_none() {
+                    self.index = self.list.len;
+                }
+                self.current = next;
+                Some(into_value(node))
+            }
+        }
+
+        pub fn ends_example() {
+            let mut list = DList::new();
+            assert_eq!(list.pop_front(), None);
+            assert_eq!(list.pop_back(), None);
+
+            list.push_back(2);
+            list.push_back(3);
+            list.push_front(1);
+            assert_eq!(list.len(), 3);
+            assert_eq!(*list.peek_front().unwrap(), 1);
+            assert_eq!(list.peek_back(), Some(3));
+            *list.peek_front_mut().unwrap() = 10;
+            assert_eq!(format!("{:?}", list), "[10, 2, 3]");
+
+            assert_eq!(list.iter().collect::<Vec<_>>(), vec![10, 2, 3]);
+            assert_eq!(list.iter().rev().collect::<Vec<_>>(), vec![3, 2, 10]);
+            let mut iter = list.iter();
+            assert_eq!((iter.next(), iter.next_back()), (Some(10), Some(3)));
+            assert_eq!((iter.next(), iter.next_back()), (Some(2), None));
+            drop(iter);
+
+            assert_eq!(list.pop_back(), Some(3));
+            assert_eq!(list.pop_front(), Some(10));
+            assert_eq!(list.pop_back(), Some(2));
+            assert!(list.is_empty());
+            assert_eq!(list.peek_back(), None);
+
+            let list: DList<i32> = (1..=5).collect();
+            let mut iter = list.into_iter();
+            assert_eq!(iter.next_back(), Some(5));
+            assert_eq!(iter.collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        }
+
+        pub fn cursor_example() {
+            let mut list: DList<i32> = (1..=5).collect();
+            let mut cursor = list.cursor_front_mut();
+            cursor.move_next();
+            cursor.move_next();
+            assert_eq!(cursor.index(), Some(2));
+            assert_eq!(cursor.remove_current(), Some(3));
+            assert_eq!(*cursor.current().unwrap(), 4);
+            cursor.insert_before(30);
+            cursor.insert_after(40);
+            assert_eq!(cursor.index(), Some(3));
+            *cursor.current_mut().unwrap() *= 10;
+            cursor.move_prev();
+            assert_eq!(*cursor.current().unwrap(), 30);
+            assert_eq!(format!("{:?}", list), "[1, 2, 30, 40, 40, 5]");
+
+            //Walk off the back into the ghost position and round to the head.
+            let mut cursor = list.cursor_back_mut();
+            assert_eq!(cursor.index(), Some(5));
+            assert_eq!(cursor.remove_current(), Some(5));
+            assert!(cursor.current().is_none());
+            assert_eq!(cursor.index(), None);
+            cursor.insert_before(50);
+            cursor.insert_after(0);
+            cursor.move_next();
+            assert_eq!(cursor.index(), Some(0));
+            assert_eq!(cursor.remove_current(), Some(0));
+            cursor.move_prev();
+            cursor.move_prev();
+            assert_eq!(*cursor.current().unwrap(), 50);
+            assert_eq!(list.len(), 6);
+            assert_eq!(list.iter().collect::<Vec<_>>(), vec![1, 2, 30, 40, 40, 50]);
+            assert_eq!(
+                list.iter().rev().collect::<Vec<_>>(),
+                vec![50, 40, 40, 30, 2, 1]
+            );
+
+            let mut list = DList::new();
+            let mut cursor = list.cursor_front_mut();
+            cursor.insert_after(2);
+            cursor.insert_after(1);
+            cursor.insert_before(3);
+            assert_eq!(list.iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+        }
+
+        //Counts its own drops so tests can see every node was freed.
+        struct Counted<'a>(&'a Cell<usize>);
+
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        pub fn no_leak_example() {
+            let dropped = Cell::new(0);
+            {
+                let mut list: DList<Counted> = (0..10).map(|_| Counted(&dropped)).collect();
+                list.pop_front();
+                list.pop_back();
+                assert_eq!(dropped.get(), 2);
+                let mut cursor = list.cursor_front_mut();
+                cursor.move_next();
+                cursor.remove_current();
+                cursor.insert_after(Counted(&dropped));
+                assert_eq!(dropped.get(), 3);
+            }
+            //10 built plus 1 inserted.
+            assert_eq!(dropped.get(), 11);
+
+            let dropped = Cell::new(0);
+            let list: DList<Counted> = (0..5).map(|_| Counted(&dropped)).collect();
+            let mut iter = list.into_iter();
+            iter.next();
+            iter.next_back();
+            drop(iter);
+            assert_eq!(dropped.get(), 5);
+
+            let mut list = DList::new();
+            for i in 0..1_000_000 {
+                list.push_back(i);
+            }
+            drop(list);
+        }
+    }
+
+    //-Rc Cycle Audit Example-------------------------------------------------/
+    //Rc cycles are never freed. The audit walks a graph from its roots
+    // through the GraphNode trait and reports counts, edges and cycles of
+    // strong references, as text or as Graphviz DOT (dot -Tsvg).
+    #[test]
+    pub fn rc_audit_tree() {
+        rc_audit::tree_example();
+    }
+
+    #[test]
+    pub fn rc_audit_cycles() {
+        rc_audit::cycle_example();
+    }
+
+    pub mod rc_audit {
+        use super::weak;
+        use std::cell::RefCell;
+        use std::collections::HashMap;
+        use std::fmt::{Display, Write};
+        use std::rc::{Rc, Weak};
+
+        pub trait GraphNode: Sized {
+            fn label(&self) -> String;
+
+            //Nodes held with Rc, eg children.
+            fn strong_edges(&self) -> Vec<Rc<Self>>;
+
+            //Nodes held with Weak, eg a parent.
+            fn weak_edges(&self) -> Vec<Weak<Self>> {
+                vec![]
+            }
+        }
+
+        //A tree only has strong edges down, so it never has a cycle.
+        impl<T: Display> GraphNode for weak::Node<T> {
+            fn label(&self) -> String {
+                self.value.to_string()
+            }
+
+            fn strong_edges(&self) -> Vec<Rc<Self>> {
+                self.children()
+            }
+
+            fn weak_edges(&self) -> Vec<Weak<Self>> {
+                self.parent().iter().map(Rc::downgrade).collect()
+            }
+        }
+
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct NodeReport {
+            pub label: String,
+            pub strong: usize,
+            pub weak: usize,
+            //Strong counts not explained by edges in the graph, eg the
+            // roots held by the caller.
+            pub external: usize,
+            //Weak edges that no longer upgrade.
+            pub dangling: usize,
+        }
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum EdgeKind {
+            Strong,
+            Weak,
+        }
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct Edge {
+            pub from: usize,
+            pub to: usize,
+            pub kind: EdgeKind,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct Cycle {
+            //Indexes into Audit::nodes, lowest first.
+            pub nodes: Vec<usize>,
+            //Only held by leaked nodes, it can never be freed.
+            pub leaked: bool,
+        }
+
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct Audit {
+            pub nodes: Vec<NodeReport>,
+            pub edges: Vec<Edge>,
+            pub cycles: Vec<Cycle>,
+        }
+
+        //The audit holds one clone of each node while walking, the counts
+        // are taken after the walk with that clone taken off.
+        pub fn audit<N: GraphNode>(roots: &[&Rc<N>]) -> Audit {
+            let mut nodes: Vec<Rc<N>> = vec![];
+            let mut index: HashMap<*const N, usize> = HashMap::new();
+            let mut edges = vec![];
+            let mut dangling = vec![];
+
+            for root in roots {
+                intern(&mut nodes, &mut index, Rc::clone(root));
+            }
+            let mut next = 0;
+            while next < nodes.len() {
+                for to in nodes[next].strong_edges() {
+                    let to = intern(&mut nodes, &mut index, to);
+                    edges.push(Edge {
+                        from: next,
+                        to,
+                        kind: EdgeKind::Strong,
+                    });
+                }
+                let mut lost = 0;
+                for to in nodes[next].weak_edges() {
+                    match to.upgrade() {
+                        Some(to) => {
+                            let to = intern(&mut nodes, &mut index, to);
+                            edges.push(Edge {
+                                from: next,
+                                to,
+                                kind: EdgeKind::Weak,
+                            });
+                        }
+                        None => lost += 1,
+                    }
+                }
+                dangling.push(lost);
+                next += 1;
+            }
+
+            let mut held = vec![0; nodes.len()];
+            for e in edges.iter().filter(|e| e.kind == EdgeKind::Strong) {
+                held[e.to] += 1;
+            }
+            let reports: Vec<NodeReport> = nodes
+                .iter()
+                .enumerate()
+                .map(|(i, n)| {
+                    let strong = Rc::strong_count(n) - 1;
+                    NodeReport {
+                        label: n.label(),
+                        strong,
+                        weak: Rc::weak_count(n),
+                        external: strong.saturating_sub(held[i]),
+                        dangling: dangling[i],
+                    }
+                })
+                .collect();
+
+            let cycles = cycles(&reports, &edges);
+            Audit {
+                nodes: reports,
+                edges,
+                cycles,
+            }
+        }
+
+        fn intern<N>(
+            nodes: &mut Vec<Rc<N>>,
+            index: &mut HashMap<*const N, usize>,
+            node: Rc<N>,
+        ) -> usize {
+            *index.entry(Rc::as_ptr(&node)).or_insert_with(|| {
+                nodes.push(node);
+                nodes.len() - 1
+            })
+        }
+
+        //Strongly connected components of the strong edges (Kosaraju),
+        // with explicit stacks so a deep graph can not overflow.
+        fn cycles(nodes: &[NodeReport], edges: &[Edge]) -> Vec<Cycle> {
+            let n = nodes.len();
+            let mut out = vec![vec![]; n];
+            let mut into = vec![vec![]; n];
+            for e in edges.iter().filter(|e| e.kind == EdgeKind::Strong) {
+                out[e.from].push(e.to);
+                into[e.to].push(e.from);
+            }
+
+            //Pass one, nodes in order of finishing.
+            let mut visited = vec![false; n];
+            let mut finished = vec![];
+            for start in 0..n {
+                if visited[start] {
+                    continue;
+                }
+                visited[start] = true;
+                let mut stack = vec![(start, 0)];
+                while let Some((v, i)) = stack.last_mut() {
+                    if let Some(&w) = out[*v].get(*i) {
+                        *i += 1;
+                        if !visited[w] {
+                            visited[w] = true;
+                            stack.push((w, 0));
+                        }
+                    } else {
+                        finished.push(*v);
+                        stack.pop();
+                    }
+                }
+            }
+
+            //Pass two, walk the reversed edges in reverse finishing order.
+            let mut component = vec![usize::MAX; n];
+            let mut components: Vec<Vec<usize>> = vec![];
+            for &start in finished.iter().rev() {
+                if component[start] != usize::MAX {
+                    continue;
+                }
+                let id = components.len();
+                component[start] = id;
+                let mut members = vec![];
+                let mut stack = vec![start];
+                while let Some(v) = stack.pop() {
+                    members.push(v);
+                    for &w in &into[v] {
+                        if component[w] == usize::MAX {
+                            component[w] = id;
+                            stack.push(w);
+                        }
+                    }
+                }
+                members.sort();
+                components.push(members);
+            }
+
+            //Live nodes are held from outside the graph, or by a live node.
+            let mut live = vec![false; n];
+            let mut stack: Vec<usize> = (0..n).filter(|&v| nodes[v].external > 0).collect();
+            while let Some(v) = stack.pop() {
+                if !live[v] {
+                    live[v] = true;
+                    stack.extend(&out[v]);
+                }
+            }
+
+            let mut cycles: Vec<Cycle> = components
+                .into_iter()
+                .filter(|c| c.len() > 1 || out[c[0]].contains(&c[0]))
+                .map(|members| Cycle {
+                    leaked: !live[members[0]],
+                    nodes: members,
+                })
+                .collect();
+            cycles.sort_by_key(|c| c.nodes[0]);
+            cycles
+        }
+
+        impl Audit {
+            pub fn in_cycle(&self, node: usize) -> bool {
+                self.cycles.iter().any(|c| c.nodes.contains(&node))
+            }
+
+            pub fn to_text(&self) -> String {
+                let mut text = String::new();
+                writeln!(text, "nodes: {}", self.nodes.len()).unwrap();
+                for (i, n) in self.nodes.iter().enumerate() {
+                    write!(
+                        text,
+                        "  n{} {:?} strong={} weak={} external={}",
+                        i, n.label, n.strong, n.weak, n.external
+                    )
+                    .unwrap();
+                    if n.dangling > 0 {
+                        write!(text, " dangling={}", n.dangling).unwrap();
+                    }
+                    writeln!(text).unwrap();
+                }
+                writeln!(text, "edges: {}", self.edges.len()).unwrap();
+                for e in &self.edges {
+                    let kind = match e.kind {
+                        EdgeKind::Strong => "strong",
+                        EdgeKind::Weak => "weak",
+                    };
+                    writeln!(text, "  n{} -> n{} {}", e.from, e.to, kind).unwrap();
+                }
+                writeln!(text, "cycles: {}", self.cycles.len()).unwrap();
+                for c in &self.cycles {
+                    let members: Vec<String> = c.nodes.iter().map(|i| format!("n{}", i)).collect();
+                    let leaked = if c.leaked { " leaked" } else { "" };
+                    writeln!(text, "  {}{}", members.join(" "), leaked).unwrap();
+                }
+                text
+            }
+
+            //Nodes in a cycle are red, weak edges are dashed.
+            pub fn to_dot(&self) -> String {
+                let mut dot = String::from("digraph rc {\n");
+                for (i, n) in self.nodes.iter().enumerate() {
+                    let label = format!(
+                        "{}\\nstrong={} weak={}",
+                        n.label.replace('\\', "\\\\").replace('"', "\\\""),
+                        n.strong,
+                        n.weak
+                    );
+                    let color = if self.in_cycle(i) { ", color=red" } else { "" };
+                    writeln!(dot, "  n{} [label=\"{}\"{}];", i, label, color).unwrap();
+                }
+                for e in &self.edges {
+                    let style = match e.kind {
+                        EdgeKind::Strong => "",
+                        EdgeKind::Weak => " [style=dashed]",
+                    };
+                    writeln!(dot, "  n{} -> n{}{};", e.from, e.to, style).unwrap();
+                }
+                dot.push_str("}\n");
+                dot
+            }
+        }
+
+        //A graph node that can hold any edges, cycles included.
+        pub struct Vertex {
+            pub name: String,
+            pub strong: RefCell<Vec<Rc<Vertex>>>,
+            pub weak: RefCell<Vec<Weak<Vertex>>>,
+        }
+
+        impl Vertex {
+            pub fn new(name: &str) -> Rc<Vertex> {
+                Rc::new(Vertex {
+                    name: String::from(name),
+                    strong: RefCell::new(vec![]),
+                    weak: RefCell::new(vec![]),
+                })
+            }
+        }
+
+        impl GraphNode for Vertex {
+            fn label(&self) -> String {
+                self.name.clone()
+            }
+
+            fn strong_edges(&self) -> Vec<Rc<Self>> {
+                self.strong.borrow().clone()
+            }
+
+            fn weak_edges(&self) -> Vec<Weak<Self>> {
+                self.weak.borrow().clone()
+            }
+        }
+
+        pub fn tree_example() {
+            let root = weak::Node::new("root");
+            let a = weak::Node::new("a");
+            root.add_child(Rc::clone(&a));
+            root.add_child(weak::Node::new("b"));
+
+            let audit = audit(&[&root]);
+            assert!(audit.cycles.is_empty());
+            assert_eq!(
+                audit.to_text(),
+                "nodes: 3\n\
+                 \x20 n0 \"root\" strong=1 weak=2 external=1\n\
+                 \x20 n1 \"a\" strong=2 weak=0 external=1\n\
+                 \x20 n2 \"b\" strong=1 weak=0 external=0\n\
+                 edges: 4\n\
+                 \x20 n0 -> n1 strong\n\
+                 \x20 n0 -> n2 strong\n\
+                 \x20 n1 -> n0 weak\n\
+                 \x20 n2 -> n0 weak\n\
+                 cycles: 0\n"
+            );
+            //The audit let go of everything it cloned.
+            assert_eq!(Rc::strong_count(&root), 1);
+            assert_eq!(Rc::strong_count(&a), 2);
+        }
+
+        pub fn cycle_example() {
+            let a = Vertex::new("a");
+            let b = Vertex::new("b");
+            let c = Vertex::new("c");
+            let d = Vertex::new("d \"quoted\"");
+            a.strong.borrow_mut().push(Rc::clone(&b));
+            b.strong.borrow_mut().push(Rc::clone(&c));
+            c.strong.borrow_mut().push(Rc::clone(&a));
+            c.strong.borrow_mut().push(Rc::clone(&d));
+            d.weak.borrow_mut().push(Rc::downgrade(&c));
+            d.strong.borrow_mut().push(Rc::clone(&d));
+
+            let report = audit(&[&a]);
+            let cycles: Vec<Vec<usize>> = report.cycles.iter().map(|c| c.nodes.clone()).collect();
+            assert_eq!(cycles, vec![vec![0, 1, 2], vec![3]]);
+            assert!(report.cycles.iter().all(|c| !c.leaked));
+            assert!(report.in_cycle(3));
+            let dot = report.to_dot();
+            assert!(dot.starts_with("digraph rc {\n"));
+            assert!(dot.contains("  n0 [label=\"a\\nstrong=2 weak=0\", color=red];\n"));
+            assert!(
+                dot.contains("  n3 [label=\"d \\\"quoted\\\"\\nstrong=3 weak=0\", color=red];\n")
+            );
+            assert!(dot.contains("  n3 -> n2 [style=dashed];\n"));
+            assert!(dot.contains("  n2 -> n0;\n"));
+
+            //Drop every outside handle to the a, b, c cycle and find it
+            // again through a weak edge: it is leaked.
+            let watcher = Vertex::new("watcher");
+            watcher.weak.borrow_mut().push(Rc::downgrade(&b));
+            let d_weak = Rc::downgrade(&d);
+            drop((a, b, c, d));
+            let report = audit(&[&watcher]);
+            assert_eq!(report.cycles.len(), 2);
+            assert!(report.cycles.iter().all(|c| c.leaked));
+            assert!(report
+                .to_text()
+                .contains("cycles: 2\n  n1 n2 n3 leaked\n  n4 leaked\n"));
+
+            //Break the cycles so the test itself does not leak.
+            let b = watcher.weak.borrow()[0].upgrade().unwrap();
+            let a = b.strong.borrow()[0].strong.borrow()[0].clone();
+            a.strong.borrow_mut().clear();
+            d_weak.upgrade().unwrap().strong.borrow_mut().clear();
+            drop((a, b));
+            let report = audit(&[&watcher]);
+            assert_eq!(report.nodes[0].dangling, 1);
+            assert!(d_weak.upgrade().is_none());
+        }
+    }
+    //-Rc<T>, RefCell<T> Example---------------------------------------------/
+    // Multiple owers of mutable data Rc<T> with RefCell<T>
+    //Cell<T> does not give references but copies the value in and out.
+    //Mutex<T> gives interior Mutability across threads.
+    #[test]
+    pub fn rc_refcell_run() {
+        rcref::rc_refcell_example();
+    }
+
+    #[test]
+    pub fn rc_refcell_shared_updates() {
+        rcref::shared_updates_example();
+    }
+
+    #[test]
+    pub fn rc_refcell_borrow_conflicts() {
+        rcref::borrow_conflict_example();
+    }
+
+    mod rcref {
+
+        //The lists share the cells, so an update through one list is seen
+        // by every list holding the same Rc<RefCell<T>>.
+        #[derive(Debug)]
+        pub enum List<T> {
+            Cons(Rc<RefCell<T>>, Rc<List<T>>),
+            Nil,
+        }
+
+        use crate::tests::rcref::List::{Cons, Nil};
+        use std::cell::{BorrowError, BorrowMutError, Ref, RefCell};
+        use std::fmt;
+        use std::rc::Rc;
+
+        //try_find_update() reads every cell and writes one, so either
+        // borrow may fail.
+        #[derive(Debug)]
+        pub enum BorrowConflict {
+            Read(BorrowError),
+            Write(BorrowMutError),
+        }
+
+        impl fmt::Display for BorrowConflict {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match self {
+                    BorrowConflict::Read(e) => write!(f, "can not read value: {}", e),
+                    BorrowConflict::Write(e) => write!(f, "can not update value: {}", e),
+                }
+            }
+        }
+
+        impl std::error::Error for BorrowConflict {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                match self {
+                    BorrowConflict::Read(e) => Some(e),
+                    BorrowConflict::Write(e) => Some(e),
+                }
+            }
+        }
+
+        impl From<BorrowError> for BorrowConflict {
+            fn from(e: BorrowError) -> BorrowConflict {
+                BorrowConflict::Read(e)
+            }
+        }
+
+        impl From<BorrowMutError> for BorrowConflict {
+            fn from(e: BorrowMutError) -> BorrowConflict {
+                BorrowConflict::Write(e)
+            }
+        }
+
+        impl<T> List<T> {
+            pub fn new() -> Rc<List<T>> {
+                Rc::new(Nil)
+            }
+
+            pub fn push_front(self: &Rc<Self>, cell: Rc<RefCell<T>>) -> Rc<List<T>> {
+                Rc::new(Cons(cell, Rc::clone(self)))
+            }
+
+            fn cells(&self) -> Cells<'_, T> {
+                Cells { next: self }
+            }
+
+            //Panics if a value is mutably borrowed, see try_iter().
+            pub fn iter(&self) -> impl Iterator<Item = Ref<'_, T>> {
+                self.cells().map(|cell| cell.borrow())
+            }
+
+            pub fn try_iter(&self) -> impl Iterator<Item = Result<Ref<'_, T>, BorrowError>> {
+                self.cells().map(|cell| cell.try_borrow())
+            }
+
+            //Update the first value matching pred, false if none match.
+            //Panics on a borrow conflict, see try_find_update().
+            pub fn find_update<P, F>(&self, pred: P, update: F) -> bool
+            where
+                P: Fn(&T) -> bool,
+                F: FnOnce(&mut T),
+            {
+                match self.cells().find(|cell| pred(&cell.borrow())) {
+                    Some(cell) => {
+                        update(&mut cell.borrow_mut());
+                        true
+                    }
+                    None => false,
+                }
+            }
+
+            pub fn try_find_update<P, F>(&self, pred: P, update: F) -> Result<bool, BorrowConflict>
+            where
+                P: Fn(&T) -> bool,
+                F: FnOnce(&mut T),
+            {
+                //Only the matching cell is borrowed mutably, shared borrows
+                // held on the others do not conflict with reading them.
+                for cell in self.cells() {
+                    let matched = pred(&*cell.try_borrow()?);
+                    if matched {
+                        update(&mut *cell.try_borrow_mut()?);
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+
+            //Update every value in place.
+            //Panics on a borrow conflict, see try_map_mut().
+            pub fn map_mut<F: FnMut(&mut T)>(&self, mut f: F) {
+                for cell in self.cells() {
+                    f(&mut cell.borrow_mut());
+                }
+            }
+
+            //Every cell is checked before any is updated, so a conflict
+            // leaves all the values unchanged.
+            pub fn try_map_mut<F: FnMut(&mut T)>(&self, mut f: F) -> Result<(), BorrowMutError> {
+                for cell in self.cells() {
+                    cell.try_borrow_mut()?;
+                }
+                for cell in self.cells() {
+                    f(&mut *cell.try_borrow_mut()?);
+                }
+                Ok(())
+            }
+        }
+
+        struct Cells<'a, T> {
+            next: &'a List<T>,
+        }
+
+        impl<'a, T> Iterator for Cells<'a, T> {
+            type Item = &'a Rc<RefCell<T>>;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                match self.next {
+                    Cons(cell, tail) => {
+                        self.next = tail;
+                        Some(cell)
+                    }
+                    Nil => None,
+                }
+            }
+        }
+
+        pub fn rc_refcell_example() {
+            let value = Rc::new(RefCell::new(5));
+
+            let a = Rc::new(Cons(Rc::clone(&value), Rc::new(Nil)));
+
+            let b = Cons(Rc::new(RefCell::new(3)), Rc::clone(&a));
+            let c = Cons(Rc::new(RefCell::new(4)), Rc::clone(&a));
+
+            *value.borrow_mut() += 10;
+
+            println!("a after = {:?}", a);
+            println!("b after = {:?}", b);
+            println!("c after = {:?}", c);
+        }
+
+        fn values<T: Clone>(list: &List<T>) -> Vec<T> {
+            list.iter().map(|v| v.clone()).collect()
+        }
+
+        pub fn shared_updates_example() {
+            let value = Rc::new(RefCell::new(5));
+            let a = List::new().push_front(Rc::clone(&value));
+            let b = a.push_front(Rc::new(RefCell::new(3)));
+            let c = a.push_front(Rc::new(RefCell::new(4)));
+
+            //Found through c, seen through a and b.
+            assert!(c.find_update(|v| *v == 5, |v| *v += 10));
+            assert!(!c.find_update(|v| *v == 3, |v| *v += 10));
+            assert_eq!(*value.borrow(), 15);
+            assert_eq!(values(&a), vec![15]);
+            assert_eq!(values(&b), vec![3, 15]);
+            assert_eq!(values(&c), vec![4, 15]);
+
+            b.map_mut(|v| *v *= 2);
+            assert_eq!(values(&b), vec![6, 30]);
+            assert_eq!(values(&c), vec![4, 30]);
+
+            assert!(c.try_find_update(|v| *v == 4, |v| *v = 0).unwrap());
+            c.try_map_mut(|v| *v += 1).unwrap();
+            assert_eq!(values(&c), vec![1, 31]);
+            assert_eq!(values(&b), vec![6, 31]);
+        }
+
+        pub fn borrow_conflict_example() {
+            let value = Rc::new(RefCell::new(5));
+            let a = List::new().push_front(Rc::clone(&value));
+            let b = a.push_front(Rc::new(RefCell::new(3)));
+
+            {
+                let _held = value.borrow_mut();
+                assert!(b.try_map_mut(|v| *v += 1).is_err());
+                assert!(matches!(
+                    b.try_find_update(|v| *v == 5, |v| *v = 0),
+                    Err(BorrowConflict::Read(_))
+                ));
+                let results: Vec<bool> = b.try_iter().map(|r| r.is_ok()).collect();
+                assert_eq!(results, vec![true, false]);
+            }
+            //Nothing was changed by the failed try_map_mut.
+            assert_eq!(values(&b), vec![3, 5]);
+
+            {
+                let _held = value.borrow();
+                assert!(b.try_find_update(|v| *v == 3, |v| *v = 1).unwrap());
+                assert!(b.try_map_mut(|v| *v += 1).is_err());
+                assert!(b.try_iter().all(|r| r.is_ok()));
+            }
+            assert_eq!(values(&b), vec![1, 5]);
+
+            //A shared borrow on an earlier, non-matching cell is no conflict.
+            {
+                let head = b.iter().next().unwrap();
+                assert!(b.find_update(|v| *v == 5, |v| *v = 6));
+                assert!(b.try_find_update(|v| *v == 6, |v| *v = 7).unwrap());
+                assert!(matches!(
+                    b.try_find_update(|v| *v == 1, |v| *v = 2),
+                    Err(BorrowConflict::Write(_))
+                ));
+                assert_eq!(*head, 1);
+            }
+            assert_eq!(values(&b), vec![1, 7]);
+        }
+    }
+
+    // RefCell<T> allows mutation of immutable references.
+    // Gives single ownership over it's data.
+
+    //Borrow Rules
+    // - At any given time, you can have either (but not both of)
+    //   one mutable reference or any number of immutable references.
+    // - References must always be valid.
+
+    //With references and Box<T>, the borrowing rules’ invariants are enforced
+    //at compile time. With RefCell<T>, these invariants are enforced at runtime.
+    //With references, if you break these rules, you’ll get a compiler error.
+    //With RefCell<T>, if you break these rules, your program will panic and exit.
+
+    // - Rc<T> enables multiple owners of the same data;
+    // - Box<T> and RefCell<T> have single owners.
+
+    // - Box<T> allows immutable or mutable borrows checked at compile time;
+    // - Rc<T> allows only immutable borrows checked at compile time;
+    // - RefCell<T> allows immutable or mutable borrows checked at runtime.
+
+    // RefCell<T> allows mutable borrows checked at runtime, allowing mutation
+    // of the value inside the RefCell<T> even when the RefCell<T> is immutable.
+
+    // & - create an immutable reference.   &mut - create a mutable reference.
+    // RefCell.borrow() for immutable reference.
+    // RefCell.borrow_mut() for mutable reference.
+    // Allows many immutable refs but only one mutable.
+    #[test]
+    pub fn refcell_example() {
+        let mock_messenger = MockMessenger::new();
+        let mut limit_tracker = LimitTracker::new(&mock_messenger, 100);
+        limit_tracker.set_value(80).unwrap();
+        assert_eq!(mock_messenger.sent_messages.borrow_mut().len(), 1);
+    }
+
+    #[test]
+    pub fn limit_tracker_default_thresholds() {
+        let mock_messenger = MockMessenger::new();
+        let mut limit_tracker = LimitTracker::new(&mock_messenger, 100);
+        for value in [10, 75, 90, 100, 150] {
+            limit_tracker.set_value(value).unwrap();
+        }
+        assert_eq!(
+            *mock_messenger.sent_messages.borrow(),
+            vec![
+                "Warning: You've used up over 75% of your quota!",
+                "Urgent warning: You've used up over 90% of your quota!",
+                "Error: You are over your quota!",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn limit_tracker_custom_thresholds() {
+        let mock_messenger = MockMessenger::new();
+        let mut limit_tracker = LimitTracker::builder(&mock_messenger, 200)
+            .threshold(0.8, Severity::Urgent, "{value} of {max} used ({percent}%)")
+            .threshold(0.5, Severity::Info, "half way: {percent}%")
+            .build();
+        limit_tracker.set_value(50).unwrap();
+        limit_tracker.set_value(120).unwrap();
+        limit_tracker.set_value(170).unwrap();
+        assert_eq!(
+            *mock_messenger.sent_messages.borrow(),
+            vec!["half way: 60%", "170 of 200 used (85%)"]
+        );
+    }
+
+    #[test]
+    pub fn limit_tracker_no_repeats() {
+        let mock_messenger = MockMessenger::new();
+        let mut limit_tracker = LimitTracker::new(&mock_messenger, 100);
+        //Dropping back to a lower level is silent without a recovered message.
+        for value in [79, 81, 76, 80, 74, 78, 91, 80] {
+            limit_tracker.set_value(value).unwrap();
+        }
+        assert_eq!(
+            *mock_messenger.sent_messages.borrow(),
+            vec![
+                "Warning: You've used up over 75% of your quota!",
+                "Warning: You've used up over 75% of your quota!",
+                "Urgent warning: You've used up over 90% of your quota!",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn limit_tracker_hysteresis() {
+        let mock_messenger = MockMessenger::new();
+        let mut limit_tracker = LimitTracker::builder(&mock_messenger, 100)
+            .hysteresis(0.05)
+            .recovered("Recovered: {percent}% of your quota used")
+            .build();
+        //74 and 71 stay within the margin of 75%, 69 leaves it.
+        for value in [80, 74, 78, 71, 76, 69, 77, 95, 88, 92, 84, 10] {
+            limit_tracker.set_value(value).unwrap();
+        }
+        assert_eq!(
+            *mock_messenger.sent_messages.borrow(),
+            vec![
+                "Warning: You've used up over 75% of your quota!",
+                "Recovered: 69% of your quota used",
+                "Warning: You've used up over 75% of your quota!",
+                "Urgent warning: You've used up over 90% of your quota!",
+                "Recovered: 84% of your quota used",
+                "Recovered: 10% of your quota used",
+            ]
+        );
+    }
+
+    pub trait Messenger {
+        fn send(&self, msg: &str);
+
+        //Override to use the severity.
+        fn send_with_severity(&self, _severity: Severity, msg: &str) {
+            self.send(msg);
+        }
+
+        //LimitTracker sends through here, override when delivery can fail.
+        fn try_send(&self, severity: Severity, msg: &str) -> Result<(), MessengerError> {
+            self.send_with_severity(severity, msg);
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum MessengerError {
+        Io(std::io::Error),
+        Disconnected,
+        Other(String),
+        GaveUp {
+            attempts: u32,
+            last: Box<MessengerError>,
+        },
+    }
+
+    impl fmt::Display for MessengerError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                MessengerError::Io(e) => write!(f, "io error: {}", e),
+                MessengerError::Disconnected => write!(f, "receiver disconnected"),
+                MessengerError::Other(msg) => write!(f, "{}", msg),
+                MessengerError::GaveUp { attempts, last } => {
+                    write!(f, "gave up after {} attempts: {}", attempts, last)
+                }
+            }
+        }
+    }
+
+    impl std::error::Error for MessengerError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                MessengerError::Io(e) => Some(e),
+                MessengerError::GaveUp { last, .. } => Some(last.as_ref()),
+                _ => None,
+            }
+        }
+    }
+
+    impl From<std::io::Error> for MessengerError {
+        fn from(e: std::io::Error) -> MessengerError {
+            MessengerError::Io(e)
+        }
+    }
+
+    use std::fmt;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum Severity {
+        Info,
+        Warning,
+        Urgent,
+        Error,
+    }
+
+    //A message is sent when value/max reaches fraction. The template may
+    // use {value}, {max} and {percent}.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Threshold {
+        pub fraction: f64,
+        pub severity: Severity,
+        pub template: String,
+    }
+
+    impl Threshold {
+        pub fn new(fraction: f64, severity: Severity, template: &str) -> Threshold {
+            Threshold {
+                fraction,
+                severity,
+                template: String::from(template),
+            }
+        }
+
+        pub fn message(&self, value: usize, max: usize) -> String {
+            render(&self.template, value, max)
+        }
+    }
+
+    fn render(template: &str, value: usize, max: usize) -> String {
+        let percent = value as f64 / max as f64 * 100.0;
+        template
+            .replace("{value}", &value.to_string())
+            .replace("{max}", &max.to_string())
+            .replace("{percent}", &format!("{:.0}", percent))
+    }
+
+    pub fn default_thresholds() -> Vec<Threshold> {
+        vec![
+            Threshold::new(
+                0.75,
+                Severity::Warning,
+                "Warning: You've used up over 75% of your quota!",
+            ),
+            Threshold::new(
+                0.9,
+                Severity::Urgent,
+                "Urgent warning: You've used up over 90% of your quota!",
+            ),
+            Threshold::new(1.0, Severity::Error, "Error: You are over your quota!"),
+        ]
+    }
+
+    pub struct LimitTracker<'a, T: Messenger> {
+        messenger: &'a T,
+        value: usize,
+        max: usize,
+        //Sorted by fraction, lowest first.
+        thresholds: Vec<Threshold>,
+        //Index of the last threshold notified, a message is only sent when
+        // the value reaches a higher one.
+        level: Option<usize>,
+        //The level is only left once the value drops hysteresis below it.
+        hysteresis: f64,
+        recovered: Option<String>,
+    }
+
+    impl<'a, T> LimitTracker<'a, T>
+    where
+        T: Messenger,
+    {
+        pub fn new(messenger: &'a T, max: usize) -> LimitTracker<'a, T> {
+            LimitTracker::builder(messenger, max).build()
+        }
+
+        pub fn builder(messenger: &'a T, max: usize) -> LimitTrackerBuilder<'a, T> {
+            LimitTrackerBuilder {
+                messenger,
+                max,
+                thresholds: vec![],
+                hysteresis: 0.0,
+                recovered: None,
+            }
+        }
+
+        //The level only changes once its message is delivered, so a failed
+        // message is sent again by the next set_value.
+        pub fn set_value(&mut self, value: usize) -> Result<(), MessengerError> {
+            self.value = value;
+
+            let percentage_of_max = self.value as f64 / self.max as f64;
+            let reached = self.reached(percentage_of_max, 0.0);
+
+            if reached > self.level {
+                let threshold = &self.thresholds[reached.unwrap()];
+                let msg = threshold.message(self.value, self.max);
+                self.messenger.try_send(threshold.severity, &msg)?;
+                self.level = reached;
+            } else {
+                let held = self.reached(percentage_of_max, self.hysteresis);
+                if held < self.level {
+                    if let Some(template) = &self.recovered {
+                        let msg = render(template, self.value, self.max);
+                        self.messenger.try_send(Severity::Info, &msg)?;
+                    }
+                    self.level = held;
+                }
+            }
+            Ok(())
+        }
+
+        pub fn value(&self) -> usize {
+            self.value
+        }
+
+        pub fn max(&self) -> usize {
+            self.max
+        }
+
+        //Back to zero without sending anything.
+        pub fn reset(&mut self) {
+            self.value = 0;
+            self.level = None;
+        }
+
+        //Index of the highest threshold at or below percentage_of_max
+        // once each fraction is lowered by margin.
+        fn reached(&self, percentage_of_max: f64, margin: f64) -> Option<usize> {
+            self.thresholds
+                .iter()
+                .rposition(|t| percentage_of_max >= t.fraction - margin)
+        }
+    }
+
+    //Adding any threshold replaces the default table.
+    pub struct LimitTrackerBuilder<'a, T: Messenger> {
+        messenger: &'a T,
+        max: usize,
+        thresholds: Vec<Threshold>,
+        hysteresis: f64,
+        recovered: Option<String>,
+    }
+
+    impl<'a, T> LimitTrackerBuilder<'a, T>
+    where
+        T: Messenger,
+    {
+        pub fn threshold(mut self, fraction: f64, severity: Severity, template: &str) -> Self {
+            self.thresholds
+                .push(Threshold::new(fraction, severity, template));
+            self
+        }
+
+        pub fn thresholds(mut self, thresholds: Vec<Threshold>) -> Self {
+            self.thresholds.extend(thresholds);
+            self
+        }
+
+        //Fraction of max the value must drop below a threshold before
+        // the tracker leaves that level.
+        pub fn hysteresis(mut self, margin: f64) -> Self {
+            self.hysteresis = margin;
+            self
+        }
+
+        //Sent when the value drops out of a level, same placeholders as
+        // the threshold templates.
+        pub fn recovered(mut self, template: &str) -> Self {
+            self.recovered = Some(String::from(template));
+            self
+        }
+
+        pub fn build(self) -> LimitTracker<'a, T> {
+            let mut thresholds = if self.thresholds.is_empty() {
+                default_thresholds()
+            } else {
+                self.thresholds
+            };
+            thresholds.sort_by(|a, b| a.fraction.total_cmp(&b.fraction));
+            LimitTracker {
+                messenger: self.messenger,
+                value: 0,
+                max: self.max,
+                thresholds,
+                level: None,
+                hysteresis: self.hysteresis,
+                recovered: self.recovered,
+            }
+        }
+    }
+
+    use std::cell::RefCell;
+
+    struct MockMessenger {
+        //Use RefCell to make inner value mutable.
+        sent_messages: RefCell<Vec<String>>,
+    }
+
+    impl MockMessenger {
+        fn new() -> MockMessenger {
+            MockMessenger {
+                sent_messages: RefCell::new(vec![]),
+            }
+        }
+    }
+
+    impl Messenger for MockMessenger {
+        fn send(&self, message: &str) {
+            //Access sent_messages Vec<String> with borrow_mut()
+            self.sent_messages.borrow_mut().push(String::from(message));
+        }
+    }
+    //-Messenger Implementations----------------------------------------------/
+    #[test]
+    pub fn write_messenger() {
+        let messenger = WriteMessenger::new(Vec::new());
+        let mut limit_tracker = LimitTracker::new(&messenger, 10);
+        limit_tracker.set_value(8).unwrap();
+        limit_tracker.set_value(10).unwrap();
+        let out = String::from_utf8(messenger.into_inner()).unwrap();
+        assert_eq!(
+            out,
+            "Warning: You've used up over 75% of your quota!\n\
+             Error: You are over your quota!\n"
+        );
+    }
+
+    #[test]
+    pub fn log_messenger() {
+        assert_eq!(log_level(Severity::Info), log::Level::Info);
+        assert_eq!(log_level(Severity::Urgent), log::Level::Warn);
+        assert_eq!(log_level(Severity::Error), log::Level::Error);
+
+        let records = Arc::new(Mutex::new(vec![]));
+        let logger = CaptureLogger {
+            records: Arc::clone(&records),
+        };
+        let messenger = LogMessenger::with_logger("quota", Box::new(logger));
+        let mut limit_tracker = LimitTracker::new(&messenger, 10);
+        limit_tracker.set_value(8).unwrap();
+        limit_tracker.set_value(9).unwrap();
+        limit_tracker.set_value(10).unwrap();
+        messenger.send("plain");
+        let records = records.lock().unwrap();
+        let levels: Vec<_> = records
+            .iter()
+            .map(|(target, level, _)| (target.as_str(), *level))
+            .collect();
+        assert_eq!(
+            levels,
+            vec![
+                ("quota", log::Level::Warn),
+                ("quota", log::Level::Warn),
+                ("quota", log::Level::Error),
+                ("quota", log::Level::Info),
+            ]
+        );
+        assert_eq!(records[2].2, "Error: You are over your quota!");
+
+        //The global logger still works, whatever it has been set to.
+        let messenger = LogMessenger::new("quota");
+        let mut limit_tracker = LimitTracker::new(&messenger, 10);
+        limit_tracker.set_value(10).unwrap();
+    }
+
+    //Records target, level and message so tests can check what was logged
+    // without replacing the global logger.
+    type Captured = Arc<Mutex<Vec<(String, log::Level, String)>>>;
+
+    struct CaptureLogger {
+        records: Captured,
+    }
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let entry = (
+                record.target().to_string(),
+                record.level(),
+                record.args().to_string(),
+            );
+            self.records.lock().unwrap().push(entry);
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    pub fn channel_messenger() {
+        use std::sync::mpsc;
+
+        let (tx, rx) = mpsc::channel();
+        let messenger = ChannelMessenger::new(tx);
+        let mut limit_tracker = LimitTracker::new(&messenger, 10);
+        limit_tracker.set_value(9).unwrap();
+        drop(limit_tracker);
+        drop(messenger);
+
+        let received: Vec<String> = rx.iter().collect();
+        assert_eq!(
+            received,
+            vec!["Urgent warning: You've used up over 90% of your quota!"]
+        );
+    }
+
+    #[test]
+    pub fn fan_out_messenger() {
+        use std::sync::mpsc;
+
+        let (tx, rx) = mpsc::channel();
+        let messenger = FanOutMessenger::new()
+            .with(Box::new(ChannelMessenger::new(tx.clone())))
+            .with(Box::new(ChannelMessenger::new(tx)))
+            .with(Box::new(LogMessenger::new("quota")));
+        let mut limit_tracker = LimitTracker::new(&messenger, 4);
+        limit_tracker.set_value(3).unwrap();
+        drop(limit_tracker);
+        drop(messenger);
+
+        assert_eq!(rx.iter().count(), 2);
+    }
+
+    //Fails the given number of sends, then delivers.
+    struct FlakyMessenger {
+        failures: Cell<u32>,
+        attempts: Cell<u32>,
+        sent: RefCell<Vec<String>>,
+    }
+
+    impl FlakyMessenger {
+        fn new(failures: u32) -> FlakyMessenger {
+            FlakyMessenger {
+                failures: Cell::new(failures),
+                attempts: Cell::new(0),
+                sent: RefCell::new(vec![]),
+            }
+        }
+    }
+
+    impl Messenger for FlakyMessenger {
+        fn send(&self, msg: &str) {
+            self.try_send(Severity::Info, msg).unwrap();
+        }
+
+        fn try_send(&self, _severity: Severity, msg: &str) -> Result<(), MessengerError> {
+            self.attempts.set(self.attempts.get() + 1);
+            if self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                return Err(MessengerError::Other(String::from("flaky")));
+            }
+            self.sent.borrow_mut().push(String::from(msg));
+            Ok(())
+        }
+    }
+
+    use std::cell::Cell;
+
+    #[test]
+    pub fn limit_tracker_surfaces_errors() {
+        let messenger = FlakyMessenger::new(1);
+        let mut limit_tracker = LimitTracker::new(&messenger, 100);
+        assert!(matches!(
+            limit_tracker.set_value(80),
+            Err(MessengerError::Other(_))
+        ));
+        //The warning was not delivered so it is sent again.
+        limit_tracker.set_value(81).unwrap();
+        assert_eq!(messenger.attempts.get(), 2);
+        assert_eq!(messenger.sent.borrow().len(), 1);
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        drop(rx);
+        let messenger = ChannelMessenger::new(tx);
+        let mut limit_tracker = LimitTracker::new(&messenger, 100);
+        assert!(matches!(
+            limit_tracker.set_value(100),
+            Err(MessengerError::Disconnected)
+        ));
+    }
+
+    #[test]
+    pub fn retry_messenger() {
+        let messenger = RetryMessenger::new(FlakyMessenger::new(2), 3)
+            .backoff(Duration::from_millis(1), Duration::from_millis(2));
+        let mut limit_tracker = LimitTracker::new(&messenger, 100);
+        limit_tracker.set_value(90).unwrap();
+        assert_eq!(messenger.inner.attempts.get(), 3);
+        assert_eq!(messenger.inner.sent.borrow().len(), 1);
+    }
+
+    #[test]
+    pub fn retry_messenger_dead_letters() {
+        let dead_letters = Rc::new(RefCell::new(vec![]));
+        let messenger = RetryMessenger::new(FlakyMessenger::new(10), 4)
+            .backoff(Duration::from_millis(1), Duration::from_millis(4))
+            .dead_letters(Box::new(Rc::clone(&dead_letters)));
+        let mut limit_tracker = LimitTracker::new(&messenger, 100);
+
+        let err = limit_tracker.set_value(100).unwrap_err();
+        assert!(matches!(err, MessengerError::GaveUp { attempts: 4, .. }));
+        assert_eq!(messenger.inner.attempts.get(), 4);
+        assert_eq!(
+            *dead_letters.borrow(),
+            vec![DeadLetter {
+                severity: Severity::Error,
+                message: String::from("Error: You are over your quota!"),
+                error: String::from("gave up after 4 attempts: flaky"),
+            }]
+        );
+    }
+
+    //Write each message as a line to any std::io::Write, eg a File,
+    // Stderr or Vec<u8>. RefCell gives the mutable access Write needs.
+    pub struct WriteMessenger<W: std::io::Write> {
+        out: RefCell<W>,
+    }
+
+    impl<W: std::io::Write> WriteMessenger<W> {
+        pub fn new(out: W) -> WriteMessenger<W> {
+            WriteMessenger {
+                out: RefCell::new(out),
+            }
+        }
+
+        pub fn into_inner(self) -> W {
+            self.out.into_inner()
+        }
+    }
+
+    impl<W: std::io::Write> Messenger for WriteMessenger<W> {
+        fn send(&self, msg: &str) {
+            if let Err(e) = self.try_send(Severity::Info, msg) {
+                log::warn!("WriteMessenger could not send {:?}: {}", msg, e);
+            }
+        }
+
+        fn try_send(&self, _severity: Severity, msg: &str) -> Result<(), MessengerError> {
+            let mut out = self.out.borrow_mut();
+            writeln!(out, "{}", msg)?;
+            out.flush()?;
+            Ok(())
+        }
+    }
+
+    pub fn log_level(severity: Severity) -> log::Level {
+        match severity {
+            Severity::Info => log::Level::Info,
+            Severity::Warning | Severity::Urgent => log::Level::Warn,
+            Severity::Error => log::Level::Error,
+        }
+    }
+
+    //Forward messages to the log crate under target, the level comes from
+    // the message severity. Plain send() logs at Info.
+    //with_logger() writes to the given logger instead of the global one.
+    pub struct LogMessenger {
+        target: String,
+        logger: Option<Box<dyn log::Log>>,
+    }
+
+    impl LogMessenger {
+        pub fn new(target: &str) -> LogMessenger {
+            LogMessenger {
+                target: String::from(target),
+                logger: None,
+            }
+        }
+
+        pub fn with_logger(target: &str, logger: Box<dyn log::Log>) -> LogMessenger {
+            LogMessenger {
+                target: String::from(target),
+                logger: Some(logger),
+            }
+        }
+    }
+
+    impl Messenger for LogMessenger {
+        fn send(&self, msg: &str) {
+            self.send_with_severity(Severity::Info, msg);
+        }
+
+        fn send_with_severity(&self, severity: Severity, msg: &str) {
+            let level = log_level(severity);
+            match &self.logger {
+                Some(logger) => logger.log(
+                    &log::Record::builder()
+                        .args(format_args!("{}", msg))
+                        .level(level)
+                        .target(&self.target)
+                        .build(),
+                ),
+                None => log::log!(target: &self.target, level, "{}", msg),
+            }
+        }
+    }
+
+    //Send each message down a channel, clone the Sender for more producers.
+    pub struct ChannelMessenger {
+        tx: std::sync::mpsc::Sender<String>,
+    }
+
+    impl ChannelMessenger {
+        pub fn new(tx: std::sync::mpsc::Sender<String>) -> ChannelMessenger {
+            ChannelMessenger { tx }
+        }
+    }
+
+    impl Messenger for ChannelMessenger {
+        fn send(&self, msg: &str) {
+            if self.try_send(Severity::Info, msg).is_err() {
+                log::warn!("ChannelMessenger receiver gone, dropped {:?}", msg);
+            }
+        }
+
+        fn try_send(&self, _severity: Severity, msg: &str) -> Result<(), MessengerError> {
+            self.tx
+                .send(String::from(msg))
+                .map_err(|_| MessengerError::Disconnected)
+        }
+    }
+
+    //Broadcast every message to each messenger in the list.
+    //Trait objects let the list hold different Messenger types.
+    #[derive(Default)]
+    pub struct FanOutMessenger {
+        messengers: Vec<Box<dyn Messenger>>,
+    }
+
+    impl FanOutMessenger {
+        pub fn new() -> FanOutMessenger {
+            FanOutMessenger::default()
+        }
+
+        pub fn with(mut self, messenger: Box<dyn Messenger>) -> Self {
+            self.messengers.push(messenger);
+            self
+        }
+    }
+
+    impl Messenger for FanOutMessenger {
+        fn send(&self, msg: &str) {
+            for messenger in &self.messengers {
+                messenger.send(msg);
+            }
+        }
+
+        fn send_with_severity(&self, severity: Severity, msg: &str) {
+            for messenger in &self.messengers {
+                messenger.send_with_severity(severity, msg);
+            }
+        }
+
+        //Every messenger is tried, the first error is returned.
+        fn try_send(&self, severity: Severity, msg: &str) -> Result<(), MessengerError> {
+            let mut result = Ok(());
+            for messenger in &self.messengers {
+                let sent = messenger.try_send(severity, msg);
+                if result.is_ok() {
+                    result = sent;
+                }
+            }
+            result
+        }
+    }
+
+    //Retry a failing messenger, sleeping backoff between attempts and
+    // doubling it each time up to max_backoff. A message still failing
+    // after max_attempts goes to the dead letter sink, if there is one.
+    pub struct RetryMessenger<M: Messenger> {
+        inner: M,
+        max_attempts: u32,
+        backoff: Duration,
+        max_backoff: Duration,
+        dead_letters: Option<Box<dyn DeadLetterSink>>,
+    }
+
+    impl<M: Messenger> RetryMessenger<M> {
+        pub fn new(inner: M, max_attempts: u32) -> RetryMessenger<M> {
+            RetryMessenger {
+                inner,
+                max_attempts: max_attempts.max(1),
+                backoff: Duration::from_millis(10),
+                max_backoff: Duration::from_secs(1),
+                dead_letters: None,
+            }
+        }
+
+        pub fn backoff(mut self, backoff: Duration, max_backoff: Duration) -> Self {
+            self.backoff = backoff;
+            self.max_backoff = max_backoff;
+            self
+        }
+
+        pub fn dead_letters(mut self, sink: Box<dyn DeadLetterSink>) -> Self {
+            self.dead_letters = Some(sink);
+            self
+        }
+    }
+
+    impl<M: Messenger> Messenger for RetryMessenger<M> {
+        fn send(&self, msg: &str) {
+            self.send_with_severity(Severity::Info, msg);
+        }
+
+        fn send_with_severity(&self, severity: Severity, msg: &str) {
+            if let Err(e) = self.try_send(severity, msg) {
+                log::warn!("RetryMessenger could not send {:?}: {}", msg, e);
+            }
+        }
+
+        fn try_send(&self, severity: Severity, msg: &str) -> Result<(), MessengerError> {
+            let mut backoff = self.backoff;
+            let mut attempt = 1;
+            loop {
+                let e = match self.inner.try_send(severity, msg) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => e,
+                };
+                debug!("RetryMessenger attempt {} failed: {}", attempt, e);
+                if attempt == self.max_attempts {
+                    let e = MessengerError::GaveUp {
+                        attempts: attempt,
+                        last: Box::new(e),
+                    };
+                    if let Some(sink) = &self.dead_letters {
+                        sink.dead_letter(severity, msg, &e);
+                    }
+                    return Err(e);
+                }
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(self.max_backoff);
+                attempt += 1;
+            }
+        }
+    }
+
+    pub trait DeadLetterSink {
+        fn dead_letter(&self, severity: Severity, msg: &str, error: &MessengerError);
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct DeadLetter {
+        pub severity: Severity,
+        pub message: String,
+        pub error: String,
+    }
+
+    //Keep dead letters in memory, clone the Rc to read them back.
+    impl DeadLetterSink for Rc<RefCell<Vec<DeadLetter>>> {
+        fn dead_letter(&self, severity: Severity, msg: &str, error: &MessengerError) {
+            self.borrow_mut().push(DeadLetter {
+                severity,
+                message: String::from(msg),
+                error: error.to_string(),
+            });
+        }
+    }
+    //-Quota Manager Example--------------------------------------------------/
+    //One LimitTracker per key sharing a single messenger. Each tracker has
+    // its own Mutex so keys are updated in parallel, the map lock is only
+    // held to find or create the tracker.
+    //The trackers borrow the messenger, use thread::scope or a 'static
+    // messenger to share the manager between threads.
+    pub struct QuotaManager<'a, K, M: Messenger> {
+        messenger: &'a M,
+        max: usize,
+        thresholds: Vec<Threshold>,
+        trackers: Mutex<HashMap<K, Arc<Mutex<LimitTracker<'a, M>>>>>,
+    }
+
+    impl<'a, K, M> QuotaManager<'a, K, M>
+    where
+        K: Hash + Eq + Clone + Display,
+        M: Messenger + Sync,
+    {
+        //Default thresholds with the key in front of each message.
+        pub fn new(messenger: &'a M, max: usize) -> QuotaManager<'a, K, M> {
+            let thresholds = default_thresholds()
+                .into_iter()
+                .map(|t| Threshold {
+                    template: format!("{{key}}: {}", t.template),
+                    ..t
+                })
+                .collect();
+            QuotaManager::with_thresholds(messenger, max, thresholds)
+        }
+
+        //Templates may use {key} as well as the LimitTracker placeholders.
+        pub fn with_thresholds(
+            messenger: &'a M,
+            max: usize,
+            thresholds: Vec<Threshold>,
+        ) -> QuotaManager<'a, K, M> {
+            QuotaManager {
+                messenger,
+                max,
+                thresholds,
+                trackers: Mutex::new(HashMap::new()),
+            }
+        }
+
+        fn tracker(&self, key: &K) -> Arc<Mutex<LimitTracker<'a, M>>> {
+            let mut trackers = self.trackers.lock().unwrap();
+            let tracker = trackers.entry(key.clone()).or_insert_with(|| {
+                let key = key.to_string();
+                let thresholds = self
+                    .thresholds
+                    .iter()
+                    .map(|t| Threshold {
+                        template: t.template.replace("{key}", &key),
+                        ..t.clone()
+                    })
+                    .collect();
+                let tracker = LimitTracker::builder(self.messenger, self.max)
+                    .thresholds(thresholds)
+                    .build();
+                Arc::new(Mutex::new(tracker))
+            });
+            Arc::clone(tracker)
+        }
+
+        //Add amount to the usage of key, returns the new usage. The amount
+        // is kept even when the message fails, see NotDelivered.
+        pub fn add(&self, key: &K, amount: usize) -> Result<usize, NotDelivered> {
+            let tracker = self.tracker(key);
+            //A panic in the messenger poisons the tracker after the new value
+            // was stored, which is still a valid state so carry on with it.
+            let mut tracker = tracker.lock().unwrap_or_else(|e| e.into_inner());
+            let usage = tracker.value() + amount;
+            match tracker.set_value(usage) {
+                Ok(()) => Ok(usage),
+                Err(error) => Err(NotDelivered { usage, error }),
+            }
+        }
+
+        pub fn usage(&self, key: &K) -> Option<usize> {
+            let tracker = self.trackers.lock().unwrap().get(key).map(Arc::clone)?;
+            let value = tracker.lock().unwrap_or_else(|e| e.into_inner()).value();
+            Some(value)
+        }
+
+        pub fn reset(&self, key: &K) {
+            let tracker = self.trackers.lock().unwrap().get(key).map(Arc::clone);
+            if let Some(tracker) = tracker {
+                tracker.lock().unwrap_or_else(|e| e.into_inner()).reset();
+            }
+        }
+
+        //Keys whose usage is at least fraction of max.
+        pub fn over(&self, fraction: f64) -> Vec<K> {
+            let trackers: Vec<_> = self
+                .trackers
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(k, t)| (k.clone(), Arc::clone(t)))
+                .collect();
+            trackers
+                .into_iter()
+                .filter(|(_, t)| {
+                    let t = t.lock().unwrap_or_else(|e| e.into_inner());
+                    t.value() as f64 / t.max() as f64 >= fraction
+                })
+                .map(|(k, _)| k)
+                .collect()
+        }
+    }
+
+    //The usage was recorded but the threshold message was not delivered.
+    // Retrying add() would count the amount twice, usage is the new total.
+    #[derive(Debug)]
+    pub struct NotDelivered {
+        pub usage: usize,
+        pub error: MessengerError,
+    }
+
+    impl fmt::Display for NotDelivered {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "usage {} recorded, message not delivered: {}",
+                self.usage, self.error
+            )
+        }
+    }
+
+    impl std::error::Error for NotDelivered {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.error)
+        }
+    }
+
+    #[test]
+    pub fn quota_manager() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let messenger = ChannelMessenger::new(tx);
+        let quotas = QuotaManager::new(&messenger, 10);
+
+        assert_eq!(quotas.add(&"alice", 8).unwrap(), 8);
+        assert_eq!(quotas.add(&"bob", 2).unwrap(), 2);
+        assert_eq!(quotas.usage(&"alice"), Some(8));
+        assert_eq!(quotas.usage(&"carol"), None);
+        assert_eq!(quotas.over(0.75), vec!["alice"]);
+
+        quotas.reset(&"alice");
+        assert_eq!(quotas.usage(&"alice"), Some(0));
+        assert!(quotas.over(0.75).is_empty());
+        quotas.add(&"alice", 8).unwrap();
+
+        drop(quotas);
+        drop(messenger);
+        let received: Vec<String> = rx.iter().collect();
+        assert_eq!(
+            received,
+            vec![
+                "alice: Warning: You've used up over 75% of your quota!",
+                "alice: Warning: You've used up over 75% of your quota!",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn quota_manager_not_delivered() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        drop(rx);
+        let messenger = ChannelMessenger::new(tx);
+        let quotas = QuotaManager::new(&messenger, 10);
+
+        let err = quotas.add(&"a", 9).unwrap_err();
+        assert_eq!(err.usage, 9);
+        assert!(matches!(err.error, MessengerError::Disconnected));
+        assert_eq!(quotas.usage(&"a"), Some(9));
+    }
+
+    struct PanicMessenger;
+
+    impl Messenger for PanicMessenger {
+        fn send(&self, msg: &str) {
+            panic!("messenger failed on {:?}", msg);
+        }
+    }
+
+    #[test]
+    pub fn quota_manager_messenger_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let messenger = PanicMessenger;
+        let quotas = QuotaManager::new(&messenger, 10);
+        quotas.add(&"b", 1).unwrap();
+        let result = catch_unwind(AssertUnwindSafe(|| quotas.add(&"a", 9)));
+        assert!(result.is_err());
+
+        //The poisoned key and the others are still usable.
+        assert_eq!(quotas.usage(&"a"), Some(9));
+        assert_eq!(quotas.over(0.75), vec!["a"]);
+        quotas.reset(&"a");
+        assert_eq!(quotas.add(&"a", 1).unwrap(), 1);
+        assert_eq!(quotas.add(&"b", 1).unwrap(), 2);
+    }
+
+    #[test]
+    pub fn quota_manager_threads() {
+        use std::thread;
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let messenger = ChannelMessenger::new(tx);
+        let quotas = QuotaManager::new(&messenger, 400);
+
+        thread::scope(|scope| {
+            for i in 0..16 {
+                let quotas = &quotas;
+                scope.spawn(move || {
+                    let key = format!("user{}", i % 4);
+                    for _ in 0..100 {
+                        quotas.add(&key, 1).unwrap();
+                    }
+                });
+            }
+        });
+
+        let mut over = quotas.over(1.0);
+        over.sort();
+        assert_eq!(over, vec!["user0", "user1", "user2", "user3"]);
+        for i in 0..4 {
+            assert_eq!(quotas.usage(&format!("user{}", i)), Some(400));
+        }
+
+        drop(quotas);
+        drop(messenger);
+        let received: Vec<String> = rx.iter().collect();
+        //Each key crosses each of the three thresholds exactly once.
+        assert_eq!(received.len(), 12);
+        for i in 0..4 {
+            let prefix = format!("user{}: ", i);
+            assert_eq!(
+                received.iter().filter(|m| m.starts_with(&prefix)).count(),
+                3
+            );
+        }
+    }
+
+    //-Rate Limiter Example---------------------------------------------------/
+    //Usage per time window reported through a LimitTracker, so the same
+    // thresholds and messengers warn as the limit is approached.
+    //Time comes from a Clock so tests can move it forward without sleeping.
+    pub trait Clock {
+        fn now(&self) -> Instant;
+    }
+
+    pub struct SystemClock;
+
+    impl Clock for SystemClock {
+        fn now(&self) -> Instant {
+            Instant::now()
+        }
+    }
+
+    impl<C: Clock + ?Sized> Clock for &C {
+        fn now(&self) -> Instant {
+            (**self).now()
+        }
+    }
+
+    //A clock that only moves when told to.
+    pub struct ManualClock {
+        now: Cell<Instant>,
+    }
+
+    impl Default for ManualClock {
+        fn default() -> Self {
+            ManualClock {
+                now: Cell::new(Instant::now()),
+            }
+        }
+    }
+
+    impl ManualClock {
+        pub fn new() -> ManualClock {
+            ManualClock::default()
+        }
+
+        pub fn advance(&self, by: Duration) {
+            self.now.set(self.now.get() + by);
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+    }
+
+    //At most tracker.max() permits in any window. Permits are taken before
+    // the tracker is told, so an Err from the messenger does not undo them.
+    pub struct SlidingWindowLimiter<'a, M: Messenger, C: Clock = SystemClock> {
+        tracker: LimitTracker<'a, M>,
+        window: Duration,
+        clock: C,
+        events: VecDeque<(Instant, usize)>,
+        used: usize,
+    }
+
+    impl<'a, M: Messenger> SlidingWindowLimiter<'a, M> {
+        pub fn new(tracker: LimitTracker<'a, M>, window: Duration) -> Self {
+            SlidingWindowLimiter::with_clock(tracker, window, SystemClock)
+        }
+    }
+
+    impl<'a, M: Messenger, C: Clock> SlidingWindowLimiter<'a, M, C> {
+        pub fn with_clock(tracker: LimitTracker<'a, M>, window: Duration, clock: C) -> Self {
+            SlidingWindowLimiter {
+                tracker,
+                window,
+                clock,
+                events: VecDeque::new(),
+                used: 0,
+            }
+        }
+
+        //Ok(true) when the permits were granted.
+        pub fn try_acquire(&mut self, permits: usize) -> Result<bool, MessengerError> {
+            let now = self.clock.now();
+            while let Some(&(at, n)) = self.events.front() {
+                if now.duration_since(at) < self.window {
+                    break;
+                }
+                self.events.pop_front();
+                self.used -= n;
+            }
+
+            let allowed = self.used + permits <= self.tracker.max();
+            if allowed {
+                self.events.push_back((now, permits));
+                self.used += permits;
+            }
+            self.tracker.set_value(self.used)?;
+            Ok(allowed)
+        }
+
+        pub fn usage(&self) -> usize {
+            self.used
+        }
+    }
+
+    //Holds up to tracker.max() tokens, refilled at rate tokens per second.
+    //The tracker sees the tokens spent, ie max minus what is left.
+    pub struct TokenBucketLimiter<'a, M: Messenger, C: Clock = SystemClock> {
+        tracker: LimitTracker<'a, M>,
+        rate: f64,
+        clock: C,
+        tokens: f64,
+        refilled: Instant,
+    }
+
+    impl<'a, M: Messenger> TokenBucketLimiter<'a, M> {
+        pub fn new(tracker: LimitTracker<'a, M>, rate: f64) -> Self {
+            TokenBucketLimiter::with_clock(tracker, rate, SystemClock)
+        }
+    }
+
+    impl<'a, M: Messenger, C: Clock> TokenBucketLimiter<'a, M, C> {
+        pub fn with_clock(tracker: LimitTracker<'a, M>, rate: f64, clock: C) -> Self {
+            let refilled = clock.now();
+            TokenBucketLimiter {
+                tokens: tracker.max() as f64,
+                tracker,
+                rate,
+                clock,
+                refilled,
+            }
+        }
+
+        //Ok(true) when the tokens were granted.
+        pub fn try_acquire(&mut self, tokens: usize) -> Result<bool, MessengerError> {
+            let now = self.clock.now();
+            let elapsed = now.duration_since(self.refilled).as_secs_f64();
+            let max = self.tracker.max() as f64;
+            self.tokens = (self.tokens + elapsed * self.rate).min(max);
+            self.refilled = now;
+
+            let allowed = self.tokens >= tokens as f64;
+            if allowed {
+                self.tokens -= tokens as f64;
+            }
+            self.tracker.set_value(self.usage())?;
+            Ok(allowed)
+        }
+
+        //Whole tokens spent.
+        pub fn usage(&self) -> usize {
+            (self.tracker.max() as f64 - self.tokens).floor() as usize
+        }
+    }
+
+    #[test]
+    pub fn sliding_window_limiter() {
+        let clock = ManualClock::new();
+        let mock_messenger = MockMessenger::new();
+        let tracker = LimitTracker::new(&mock_messenger, 10);
+        let mut limiter =
+            SlidingWindowLimiter::with_clock(tracker, Duration::from_secs(60), &clock);
+
+        assert!(limiter.try_acquire(5).unwrap());
+        clock.advance(Duration::from_secs(30));
+        assert!(limiter.try_acquire(3).unwrap());
+        assert!(limiter.try_acquire(2).unwrap());
+        assert!(!limiter.try_acquire(1).unwrap());
+        assert_eq!(limiter.usage(), 10);
+
+        //The first 5 leave the window.
+        clock.advance(Duration::from_secs(30));
+        assert!(limiter.try_acquire(4).unwrap());
+        assert_eq!(limiter.usage(), 9);
+        assert_eq!(
+            *mock_messenger.sent_messages.borrow(),
+            vec![
+                "Warning: You've used up over 75% of your quota!",
+                "Error: You are over your quota!",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn token_bucket_limiter() {
+        let clock = ManualClock::new();
+        let mock_messenger = MockMessenger::new();
+        let tracker = LimitTracker::builder(&mock_messenger, 10)
+            .recovered("Recovered: {value} of {max} tokens used")
+            .build();
+        let mut limiter = TokenBucketLimiter::with_clock(tracker, 2.0, &clock);
+
+        assert!(limiter.try_acquire(8).unwrap());
+        assert!(!limiter.try_acquire(3).unwrap());
+        assert_eq!(limiter.usage(), 8);
+
+        //2 tokens a second, 1.5 seconds refills 3.
+        clock.advance(Duration::from_millis(1500));
+        assert!(limiter.try_acquire(3).unwrap());
+        assert_eq!(limiter.usage(), 8);
+
+        clock.advance(Duration::from_secs(60));
+        assert!(limiter.try_acquire(1).unwrap());
+        assert_eq!(limiter.usage(), 1);
+        assert_eq!(
+            *mock_messenger.sent_messages.borrow(),
+            vec![
+                "Warning: You've used up over 75% of your quota!",
+                "Recovered: 1 of 10 tokens used",
+            ]
+        );
+    }
+
+    //-Tracker Event Log Example----------------------------------------------/
+    //Every change to the tracker is appended to a log file so the value and
+    // the last level notified survive a restart:
+    //  rustex-tracker 1
+    //  1700000000 snapshot 0 -
+    //  1700000005 set 80 0
+    //  1700000009 reset
+    //Each event has the unix time it was written, the level is an index
+    // into the tracker thresholds or - for none.
+    //Opening replays the log without sending anything. After compact_every
+    // events the log is rewritten as a single snapshot, the events it
+    // replaces are first appended to an archive beside it (tracker.archive
+    // for tracker.log) in the same format, so the history can be audited.
+    const TRACKER_LOG_HEADER: &str = "rustex-tracker 1";
+
+    #[derive(Debug)]
+    pub enum TrackerLogError {
+        Io(std::io::Error),
+        Corrupt { line: usize, text: String },
+        Messenger(MessengerError),
+    }
+
+    impl fmt::Display for TrackerLogError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                TrackerLogError::Io(e) => write!(f, "io error: {}", e),
+                TrackerLogError::Corrupt { line, text } => {
+                    write!(f, "corrupt event on line {}: {:?}", line, text)
+                }
+                TrackerLogError::Messenger(e) => write!(f, "messenger error: {}", e),
+            }
+        }
+    }
+
+    impl std::error::Error for TrackerLogError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                TrackerLogError::Io(e) => Some(e),
+                TrackerLogError::Messenger(e) => Some(e),
+                TrackerLogError::Corrupt { .. } => None,
+            }
+        }
+    }
+
+    impl From<std::io::Error> for TrackerLogError {
+        fn from(e: std::io::Error) -> TrackerLogError {
+            TrackerLogError::Io(e)
+        }
+    }
+
+    impl From<MessengerError> for TrackerLogError {
+        fn from(e: MessengerError) -> TrackerLogError {
+            TrackerLogError::Messenger(e)
+        }
+    }
+
+    pub struct LoggedTracker<'a, M: Messenger> {
+        tracker: LimitTracker<'a, M>,
+        path: PathBuf,
+        out: std::fs::File,
+        events: usize,
+        compact_every: usize,
+    }
+
+    use std::path::PathBuf;
+
+    impl<'a, M: Messenger> LoggedTracker<'a, M> {
+        //Restore tracker from the log at path, creating it if missing.
+        pub fn open(
+            mut tracker: LimitTracker<'a, M>,
+            path: &Path,
+        ) -> Result<LoggedTracker<'a, M>, TrackerLogError> {
+            let contents = match std::fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(e.into()),
+            };
+
+            //A crash part way through an append leaves a line without a
+            // newline, drop it and rewrite the log below.
+            let (complete, torn) = match contents.rfind('\n') {
+                Some(i) => (&contents[..=i], &contents[i + 1..]),
+                None => ("", &contents[..]),
+            };
+            if !torn.is_empty() {
+                warn!("Tracker log {:?} ignoring torn event {:?}", path, torn);
+            }
+
+            let mut events = 0;
+            for (n, line) in complete.lines().enumerate() {
+                if n == 0 && line == TRACKER_LOG_HEADER {
+                    continue;
+                }
+                if !replay(&mut tracker, line) {
+                    return Err(TrackerLogError::Corrupt {
+                        line: n + 1,
+                        text: String::from(line),
+                    });
+                }
+                //Counted as compact() leaves it, a snapshot is not an event.
+                if line.split(' ').nth(1) != Some("snapshot") {
+                    events += 1;
+                }
+            }
+
+            let out = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            let mut logged = LoggedTracker {
+                tracker,
+                path: path.to_path_buf(),
+                out,
+                events,
+                compact_every: 1000,
+            };
+            if !complete.starts_with(TRACKER_LOG_HEADER) || !torn.is_empty() {
+                logged.compact()?;
+            }
+            Ok(logged)
+        }
+
+        pub fn compact_every(mut self, events: usize) -> Self {
+            self.compact_every = events.max(1);
+            self
+        }
+
+        pub fn tracker(&self) -> &LimitTracker<'a, M> {
+            &self.tracker
+        }
+
+        //The event is logged even when the messenger fails, the tracker
+        // level is unchanged in that case and is logged as such.
+        pub fn set_value(&mut self, value: usize) -> Result<(), TrackerLogError> {
+            let sent = self.tracker.set_value(value);
+            let level = level_field(self.tracker.level);
+            self.append(&format!("set {} {}", self.tracker.value, level))?;
+            Ok(sent?)
+        }
+
+        pub fn reset(&mut self) -> Result<(), TrackerLogError> {
+            self.tracker.reset();
+            self.append("reset")
+        }
+
+        fn append(&mut self, event: &str) -> Result<(), TrackerLogError> {
+            writeln!(self.out, "{} {}", unix_time(), event)?;
+            self.out.flush()?;
+            self.events += 1;
+            if self.events >= self.compact_every {
+                self.compact()?;
+            }
+            Ok(())
+        }
+
+        pub fn archive_path(&self) -> PathBuf {
+            self.path.with_extension("archive")
+        }
+
+        //Replace the log with the current state, written to a temporary
+        // file then renamed over the log. A crash before the rename may
+        // archive the same events again on the next compaction.
+        pub fn compact(&mut self) -> Result<(), TrackerLogError> {
+            self.archive()?;
+            let tmp = self.path.with_extension("tmp");
+            {
+                let mut out = BufWriter::new(std::fs::File::create(&tmp)?);
+                writeln!(out, "{}", TRACKER_LOG_HEADER)?;
+                writeln!(
+                    out,
+                    "{} snapshot {} {}",
+                    unix_time(),
+                    self.tracker.value,
+                    level_field(self.tracker.level)
+                )?;
+                out.flush()?;
+            }
+            std::fs::rename(&tmp, &self.path)?;
+            self.out = std::fs::OpenOptions::new().append(true).open(&self.path)?;
+            self.events = 0;
+            debug!("Tracker log {:?} compacted", self.path);
+            Ok(())
+        }
+
+        //Append the complete events in the log to the archive, a torn last
+        // line is left out as it is on replay.
+        fn archive(&self) -> Result<(), TrackerLogError> {
+            let contents = match std::fs::read_to_string(&self.path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            let complete = match contents.rfind('\n') {
+                Some(i) => &contents[..=i],
+                None => return Ok(()),
+            };
+            let events = match complete.strip_prefix(TRACKER_LOG_HEADER) {
+                Some(rest) => rest.strip_prefix('\n').unwrap_or(rest),
+                None => complete,
+            };
+            if events.is_empty() {
+                return Ok(());
+            }
+
+            let mut out = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.archive_path())?;
+            if out.metadata()?.len() == 0 {
+                writeln!(out, "{}", TRACKER_LOG_HEADER)?;
+            }
+            out.write_all(events.as_bytes())?;
+            out.flush()?;
+            Ok(())
+        }
+    }
+
+    fn unix_time() -> u64 {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    fn level_field(level: Option<usize>) -> String {
+        match level {
+            Some(level) => level.to_string(),
+            None => String::from("-"),
+        }
+    }
+
+    //Apply one event to tracker, false if the line is not a valid event.
+    fn replay<M: Messenger>(tracker: &mut LimitTracker<M>, line: &str) -> bool {
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields.is_empty() || fields[0].parse::<u64>().is_err() {
+            return false;
+        }
+        match fields[1..] {
+            ["reset"] => {
+                tracker.reset();
+                true
+            }
+            ["set", value, level] | ["snapshot", value, level] => {
+                let level = match level {
+                    "-" => None,
+                    level => match level.parse::<usize>() {
+                        Ok(level) if level < tracker.thresholds.len() => Some(level),
+                        _ => return false,
+                    },
+                };
+                match value.parse() {
+                    Ok(value) => {
+                        tracker.value = value;
+                        tracker.level = level;
+                        true
+                    }
+                    Err(_) => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    #[test]
+    pub fn logged_tracker_restores_state() {
+        let path = snapshot_path("tracker-restore");
+        let mock_messenger = MockMessenger::new();
+        {
+            let tracker = LimitTracker::new(&mock_messenger, 100);
+            let mut logged = LoggedTracker::open(tracker, &path).unwrap();
+            logged.set_value(50).unwrap();
+            logged.set_value(80).unwrap();
+        }
+        assert_eq!(mock_messenger.sent_messages.borrow().len(), 1);
+
+        let mock_messenger = MockMessenger::new();
+        let tracker = LimitTracker::new(&mock_messenger, 100);
+        let mut logged = LoggedTracker::open(tracker, &path).unwrap();
+        assert_eq!(logged.tracker().value(), 80);
+
+        //The 75% warning was already sent before the restart.
+        logged.set_value(85).unwrap();
+        logged.set_value(95).unwrap();
+        logged.reset().unwrap();
+        logged.set_value(80).unwrap();
+        assert_eq!(
+            *mock_messenger.sent_messages.borrow(),
+            vec![
+                "Urgent warning: You've used up over 90% of your quota!",
+                "Warning: You've used up over 75% of your quota!",
+            ]
+        );
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let events: Vec<&str> = log
+            .lines()
+            .skip(1)
+            .map(|l| l.split_once(' ').unwrap().1)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                "snapshot 0 -",
+                "set 50 -",
+                "set 80 0",
+                "set 85 0",
+                "set 95 1",
+                "reset",
+                "set 80 0"
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    pub fn logged_tracker_compacts() {
+        let path = snapshot_path("tracker-compact");
+        let mock_messenger = MockMessenger::new();
+        let tracker = LimitTracker::new(&mock_messenger, 100);
+        let mut logged = LoggedTracker::open(tracker, &path)
+            .unwrap()
+            .compact_every(3);
+        for value in [10, 20, 30, 40, 50, 60, 92] {
+            logged.set_value(value).unwrap();
+        }
+        drop(logged);
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(log.lines().count(), 3);
+        assert!(log.lines().nth(1).unwrap().ends_with(" snapshot 60 -"));
+        assert!(log.lines().nth(2).unwrap().ends_with(" set 92 1"));
+
+        let tracker = LimitTracker::new(&mock_messenger, 100);
+        let logged = LoggedTracker::open(tracker, &path).unwrap();
+        assert_eq!(logged.tracker().value(), 92);
+        assert_eq!(logged.tracker().level, Some(1));
+
+        //Replaying the snapshot does not count towards the next compaction,
+        // one more event leaves set 92 and set 93 after the snapshot.
+        drop(logged);
+        let tracker = LimitTracker::new(&mock_messenger, 100);
+        let mut logged = LoggedTracker::open(tracker, &path)
+            .unwrap()
+            .compact_every(3);
+        logged.set_value(93).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 4);
+
+        //Every compacted event is still in the archive, which replays to
+        // the state at the last compaction.
+        let archive = logged.archive_path();
+        let log = std::fs::read_to_string(&archive).unwrap();
+        assert_eq!(log.lines().next(), Some(TRACKER_LOG_HEADER));
+        let events: Vec<&str> = log
+            .lines()
+            .skip(1)
+            .map(|l| l.split_once(' ').unwrap().1)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                "snapshot 0 -",
+                "set 10 -",
+                "set 20 -",
+                "set 30 -",
+                "snapshot 30 -",
+                "set 40 -",
+                "set 50 -",
+                "set 60 -",
+            ]
+        );
+        drop(logged);
+        std::fs::copy(&archive, &path).unwrap();
+        let tracker = LimitTracker::new(&mock_messenger, 100);
+        let logged = LoggedTracker::open(tracker, &path).unwrap();
+        assert_eq!(logged.tracker().value(), 60);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&archive).unwrap();
+    }
+
+    #[test]
+    pub fn logged_tracker_bad_logs() {
+        let path = snapshot_path("tracker-bad");
+        let mock_messenger = MockMessenger::new();
+
+        use std::error::Error;
+        let e = TrackerLogError::from(MessengerError::Disconnected);
+        assert_eq!(e.source().unwrap().to_string(), "receiver disconnected");
+
+        std::fs::write(&path, "rustex-tracker 1\n1 set 80 0\n1 set 9").unwrap();
+        let tracker = LimitTracker::new(&mock_messenger, 100);
+        let logged = LoggedTracker::open(tracker, &path).unwrap();
+        assert_eq!(logged.tracker().value(), 80);
+        let archive = logged.archive_path();
+        drop(logged);
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .ends_with(" snapshot 80 0\n"));
+        assert_eq!(
+            std::fs::read_to_string(&archive).unwrap(),
+            "rustex-tracker 1\n1 set 80 0\n"
+        );
+        std::fs::remove_file(&archive).unwrap();
+
+        for bad in ["1 set 80 7\n", "1 set x -\n", "set 80 0\n", "1 add 3\n"] {
+            std::fs::write(&path, format!("rustex-tracker 1\n{}", bad)).unwrap();
+            let tracker = LimitTracker::new(&mock_messenger, 100);
+            assert!(matches!(
+                LoggedTracker::open(tracker, &path),
+                Err(TrackerLogError::Corrupt { line: 2, .. })
+            ));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    //-Socket Messenger Example-----------------------------------------------/
+    //Messages go to another process as newline delimited lines over TCP or
+    // a Unix domain socket, escaped like the Cacher snapshot lines.
+    //A dropped connection is noticed before writing, or when the write
+    // fails, and the messenger connects again.
+    #[test]
+    pub fn socket_messenger_tcp() {
+        socket::tcp_example();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    pub fn socket_messenger_unix() {
+        socket::unix_example();
+    }
+
+    pub mod socket {
+        use super::{escape, unescape, LimitTracker, Messenger, MessengerError, Severity};
+        use std::cell::RefCell;
+        use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+        use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+        #[cfg(unix)]
+        use std::os::unix::net::{UnixListener, UnixStream};
+        #[cfg(unix)]
+        use std::path::PathBuf;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::mpsc;
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+        use std::time::Duration;
+
+        #[derive(Debug, Clone)]
+        pub enum SocketAddress {
+            Tcp(SocketAddr),
+            #[cfg(unix)]
+            Unix(PathBuf),
+        }
+
+        impl SocketAddress {
+            fn connect(&self) -> io::Result<Connection> {
+                match self {
+                    SocketAddress::Tcp(addr) => Ok(Connection::Tcp(TcpStream::connect(addr)?)),
+                    #[cfg(unix)]
+                    SocketAddress::Unix(path) => Ok(Connection::Unix(UnixStream::connect(path)?)),
+                }
+            }
+        }
+
+        enum Connection {
+            Tcp(TcpStream),
+            #[cfg(unix)]
+            Unix(UnixStream),
+        }
+
+        impl Connection {
+            fn try_clone(&self) -> io::Result<Connection> {
+                match self {
+                    Connection::Tcp(s) => Ok(Connection::Tcp(s.try_clone()?)),
+                    #[cfg(unix)]
+                    Connection::Unix(s) => Ok(Connection::Unix(s.try_clone()?)),
+                }
+            }
+
+            fn shutdown(&self) -> io::Result<()> {
+                match self {
+                    Connection::Tcp(s) => s.shutdown(Shutdown::Both),
+                    #[cfg(unix)]
+                    Connection::Unix(s) => s.shutdown(Shutdown::Both),
+                }
+            }
+
+            fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+                match self {
+                    Connection::Tcp(s) => s.set_nonblocking(nonblocking),
+                    #[cfg(unix)]
+                    Connection::Unix(s) => s.set_nonblocking(nonblocking),
+                }
+            }
+
+            //The receiver never writes back, so a read that does not
+            // block means the other end has closed.
+            fn is_closed(&mut self) -> bool {
+                if self.set_nonblocking(true).is_err() {
+                    return true;
+                }
+                let closed = match self.read(&mut [0u8; 1]) {
+                    Err(e) => e.kind() != ErrorKind::WouldBlock,
+                    Ok(n) => n == 0,
+                };
+                self.set_nonblocking(false).is_err() || closed
+            }
+        }
+
+        impl Read for Connection {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self {
+                    Connection::Tcp(s) => s.read(buf),
+                    #[cfg(unix)]
+                    Connection::Unix(s) => s.read(buf),
+                }
+            }
+        }
+
+        impl Write for Connection {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                match self {
+                    Connection::Tcp(s) => s.write(buf),
+                    #[cfg(unix)]
+                    Connection::Unix(s) => s.write(buf),
+                }
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                match self {
+                    Connection::Tcp(s) => s.flush(),
+                    #[cfg(unix)]
+                    Connection::Unix(s) => s.flush(),
+                }
+            }
+        }
+
+        pub struct SocketMessenger {
+            address: SocketAddress,
+            connection: RefCell<Option<Connection>>,
+        }
+
+        impl SocketMessenger {
+            //Connects on the first message.
+            pub fn new(address: SocketAddress) -> SocketMessenger {
+                SocketMessenger {
+                    address,
+                    connection: RefCell::new(None),
+                }
+            }
+        }
+
+        impl Messenger for SocketMessenger {
+            fn send(&self, msg: &str) {
+                if let Err(e) = self.try_send(Severity::Info, msg) {
+                    log::warn!("SocketMessenger could not send {:?}: {}", msg, e);
+                }
+            }
+
+            fn try_send(&self, _severity: Severity, msg: &str) -> Result<(), MessengerError> {
+                let line = format!("{}\n", escape(msg));
+                let mut connection = self.connection.borrow_mut();
+
+                if let Some(c) = connection.as_mut() {
+                    if !c.is_closed() && c.write_all(line.as_bytes()).is_ok() {
+                        return Ok(());
+                    }
+                    log::debug!("SocketMessenger reconnecting to {:?}", self.address);
+                    *connection = None;
+                }
+
+                let mut c = self.address.connect()?;
+                c.write_all(line.as_bytes())?;
+                *connection = Some(c);
+                Ok(())
+            }
+        }
+
+        enum Listener {
+            Tcp(TcpListener),
+            #[cfg(unix)]
+            Unix(UnixListener),
+        }
+
+        impl Listener {
+            fn accept(&self) -> io::Result<Connection> {
+                match self {
+                    Listener::Tcp(l) => Ok(Connection::Tcp(l.accept()?.0)),
+                    #[cfg(unix)]
+                    Listener::Unix(l) => Ok(Connection::Unix(l.accept()?.0)),
+                }
+            }
+        }
+
+        //Test receiver, each line from each connection is unescaped and
+        // queued for recv_timeout(). Dropping it closes everything.
+        pub struct MessageReceiver {
+            address: SocketAddress,
+            messages: mpsc::Receiver<String>,
+            connections: Arc<Mutex<Vec<Connection>>>,
+            stop: Arc<AtomicBool>,
+            accept: Option<thread::JoinHandle<()>>,
+        }
+
+        impl MessageReceiver {
+            //Bind to a free localhost port.
+            pub fn bind_tcp() -> io::Result<MessageReceiver> {
+                let listener = TcpListener::bind("127.0.0.1:0")?;
+                listener.set_nonblocking(true)?;
+                let address = SocketAddress::Tcp(listener.local_addr()?);
+                Ok(MessageReceiver::start(address, Listener::Tcp(listener)))
+            }
 
-    pub struct LimitTracker<'a, T: Messenger> {
-        messenger: &'a T,
-        value: usize,
-        max: usize,
-    }
+            #[cfg(unix)]
+            pub fn bind_unix(path: &std::path::Path) -> io::Result<MessageReceiver> {
+                let listener = UnixListener::bind(path)?;
+                listener.set_nonblocking(true)?;
+                let address = SocketAddress::Unix(path.to_path_buf());
+                Ok(MessageReceiver::start(address, Listener::Unix(listener)))
+            }
 
-    impl<'a, T> LimitTracker<'a, T>
-    where
-        T: Messenger,
-    {
-        pub fn new(messenger: &T, max: usize) -> LimitTracker<T> {
-            LimitTracker {
-                messenger,
-                value: 0,
-                max,
+            fn start(address: SocketAddress, listener: Listener) -> MessageReceiver {
+                let (tx, messages) = mpsc::channel();
+                let connections = Arc::new(Mutex::new(vec![]));
+                let stop = Arc::new(AtomicBool::new(false));
+
+                let accept = {
+                    let connections = Arc::clone(&connections);
+                    let stop = Arc::clone(&stop);
+                    thread::spawn(move || {
+                        while !stop.load(Ordering::SeqCst) {
+                            let connection = match listener.accept() {
+                                Ok(c) => c,
+                                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                                    thread::sleep(Duration::from_millis(5));
+                                    continue;
+                                }
+                                Err(e) => {
+                                    log::warn!("MessageReceiver accept failed: {}", e);
+                                    break;
+                                }
+                            };
+                            if connection.set_nonblocking(false).is_err() {
+                                continue;
+                            }
+                            if let Ok(c) = connection.try_clone() {
+                                connections.lock().unwrap().push(c);
+                            }
+                            let tx = tx.clone();
+                            thread::spawn(move || {
+                                for line in BufReader::new(connection).lines() {
+                                    let msg = match line.ok().as_deref().and_then(unescape) {
+                                        Some(msg) => msg,
+                                        None => break,
+                                    };
+                                    if tx.send(msg).is_err() {
+                                        break;
+                                    }
+                                }
+                            });
+                        }
+                    })
+                };
+
+                MessageReceiver {
+                    address,
+                    messages,
+                    connections,
+                    stop,
+                    accept: Some(accept),
+                }
             }
-        }
 
-        pub fn set_value(&mut self, value: usize) {
-            self.value = value;
+            pub fn address(&self) -> SocketAddress {
+                self.address.clone()
+            }
 
-            let percentage_of_max = self.value as f64 / self.max as f64;
+            pub fn recv_timeout(&self, timeout: Duration) -> Option<String> {
+                self.messages.recv_timeout(timeout).ok()
+            }
 
-            if percentage_of_max >= 1.0 {
-                self.messenger.send("Error: You are over your quota!");
-            } else if percentage_of_max >= 0.9 {
-                self.messenger
-                    .send("Urgent warning: You've used up over 90% of your quota!");
-            } else if percentage_of_max >= 0.75 {
-                self.messenger
-                    .send("Warning: You've used up over 75% of your quota!");
+            //Close every open connection, the listener keeps accepting.
+            pub fn drop_connections(&self) {
+                for c in self.connections.lock().unwrap().drain(..) {
+                    let _ = c.shutdown();
+                }
             }
         }
-    }
 
-    use std::cell::RefCell;
+        impl Drop for MessageReceiver {
+            fn drop(&mut self) {
+                self.stop.store(true, Ordering::SeqCst);
+                if let Some(accept) = self.accept.take() {
+                    let _ = accept.join();
+                }
+                self.drop_connections();
+                #[cfg(unix)]
+                if let SocketAddress::Unix(path) = &self.address {
+                    let _ = std::fs::remove_file(path);
+                }
+            }
+        }
 
-    struct MockMessenger {
-        //Use RefCell to make inner value mutable.
-        sent_messages: RefCell<Vec<String>>,
-    }
+        const TIMEOUT: Duration = Duration::from_secs(5);
 
-    impl MockMessenger {
-        fn new() -> MockMessenger {
-            MockMessenger {
-                sent_messages: RefCell::new(vec![]),
-            }
+        pub fn tcp_example() {
+            let receiver = MessageReceiver::bind_tcp().unwrap();
+            let messenger = SocketMessenger::new(receiver.address());
+            let mut limit_tracker = LimitTracker::builder(&messenger, 100)
+                .recovered("Recovered:\n{percent}%")
+                .build();
+
+            limit_tracker.set_value(80).unwrap();
+            limit_tracker.set_value(95).unwrap();
+            assert_eq!(
+                receiver.recv_timeout(TIMEOUT).unwrap(),
+                "Warning: You've used up over 75% of your quota!"
+            );
+            assert_eq!(
+                receiver.recv_timeout(TIMEOUT).unwrap(),
+                "Urgent warning: You've used up over 90% of your quota!"
+            );
+
+            //The messenger notices the closed connection and reconnects.
+            receiver.drop_connections();
+            limit_tracker.set_value(10).unwrap();
+            assert_eq!(receiver.recv_timeout(TIMEOUT).unwrap(), "Recovered:\n10%");
         }
-    }
 
-    impl Messenger for MockMessenger {
-        fn send(&self, message: &str) {
-            //Access sent_messages Vec<String> with borrow_mut()
-            self.sent_messages.borrow_mut().push(String::from(message));
+        #[cfg(unix)]
+        pub fn unix_example() {
+            let path = std::env::temp_dir().join(format!("rustex-{}.sock", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+
+            let receiver = MessageReceiver::bind_unix(&path).unwrap();
+            let messenger = SocketMessenger::new(receiver.address());
+            let mut limit_tracker = LimitTracker::new(&messenger, 10);
+            limit_tracker.set_value(8).unwrap();
+            assert_eq!(
+                receiver.recv_timeout(TIMEOUT).unwrap(),
+                "Warning: You've used up over 75% of your quota!"
+            );
+
+            //Nothing listening, the error reaches the caller and the
+            // level is unchanged so the message is sent again later.
+            drop(receiver);
+            assert!(matches!(
+                limit_tracker.set_value(10),
+                Err(MessengerError::Io(_))
+            ));
+
+            let receiver = MessageReceiver::bind_unix(&path).unwrap();
+            limit_tracker.set_value(10).unwrap();
+            assert_eq!(
+                receiver.recv_timeout(TIMEOUT).unwrap(),
+                "Error: You are over your quota!"
+            );
         }
     }
+
     //-Dereference Operator Example----------------------------------------------/
     // Deref coercion is a convenience that Rust performs on args to functions and
     //   methods on types that implement the Deref Trait. eg &String to &str.
@@ -447,38 +4715,311 @@ pub mod tests {
         println!("hello, {}!", name);
     }
 
-    #[derive(Debug)]
-    struct MyBox<T: std::fmt::Debug>(T);
+    //The comparison, hashing and Clone derives delegate to T.
+    #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct MyBox<T>(T);
 
-    use std::ops::Deref;
+    use std::ops::{Deref, DerefMut};
 
-    impl<T: std::fmt::Debug> Deref for MyBox<T> {
+    impl<T> Deref for MyBox<T> {
         type Target = T;
 
         fn deref(&self) -> &Self::Target {
             &self.0
         }
     }
+
+    impl<T> DerefMut for MyBox<T> {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.0
+        }
+    }
+
+    impl<T> AsRef<T> for MyBox<T> {
+        fn as_ref(&self) -> &T {
+            &self.0
+        }
+    }
+
+    impl<T> AsMut<T> for MyBox<T> {
+        fn as_mut(&mut self) -> &mut T {
+            &mut self.0
+        }
+    }
+
+    //Borrow promises Eq, Ord and Hash agree with T, which the derives give,
+    // so a HashMap<MyBox<K>, V> can be looked up by &K.
+    impl<T> std::borrow::Borrow<T> for MyBox<T> {
+        fn borrow(&self) -> &T {
+            &self.0
+        }
+    }
+
+    impl<T: std::fmt::Display> std::fmt::Display for MyBox<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+
+    impl<T> From<T> for MyBox<T> {
+        fn from(x: T) -> MyBox<T> {
+            MyBox(x)
+        }
+    }
+
+    #[test]
+    pub fn mybox_traits() {
+        use std::collections::HashMap;
+
+        let mut b = MyBox::new(String::from("Rust"));
+        b.push_str("acean");
+        b.as_mut().make_ascii_uppercase();
+        assert_eq!(b.as_ref(), "RUSTACEAN");
+        assert_eq!(format!("{} {:?}", b, b), "RUSTACEAN MyBox(\"RUSTACEAN\")");
+
+        let c = b.clone();
+        assert_eq!(b, c);
+        assert!(MyBox::from(1) < MyBox::new(2));
+        assert_eq!(MyBox::new(3).max(MyBox::new(2)), MyBox(3));
+
+        let mut counts = HashMap::new();
+        counts.insert(MyBox::new(7), "seven");
+        assert_eq!(counts.get(&7), Some(&"seven"));
+        assert_eq!(c.into_inner(), "RUSTACEAN");
+    }
     //-Drop Example-----------------------------------------------------------/
     #[test]
     pub fn drop_example() {
         let x = 5;
-        let y = MyBox::new(x);
+        let y = LoggedBox::new(x);
         println!("x:{}, y:{:?}", x, y);
         //Note: can not call y.drop() manuall so use drop(y) from std::mem
         // to force early cleanup. drop is in the prelude.
     }
 
-    impl<T: std::fmt::Debug> MyBox<T> {
-        fn new(x: T) -> MyBox<T> {
+    impl<T> MyBox<T> {
+        pub fn new(x: T) -> MyBox<T> {
             MyBox(x)
         }
+
+        pub fn into_inner(self) -> T {
+            self.0
+        }
+    }
+
+    //Opt-in drop logging, MyBox itself has no Drop so it can be moved out of.
+    #[derive(Debug)]
+    pub struct LoggedBox<T: std::fmt::Debug>(MyBox<T>);
+
+    impl<T: std::fmt::Debug> LoggedBox<T> {
+        pub fn new(x: T) -> LoggedBox<T> {
+            LoggedBox(MyBox::new(x))
+        }
+    }
+
+    impl<T: std::fmt::Debug> Deref for LoggedBox<T> {
+        type Target = T;
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    impl<T: std::fmt::Debug> DerefMut for LoggedBox<T> {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.0
+        }
     }
 
     //Note the Drop trait is included in the prelude
-    impl<T: std::fmt::Debug> Drop for MyBox<T> {
+    impl<T: std::fmt::Debug> Drop for LoggedBox<T> {
         fn drop(&mut self) {
-            println!("Dropping MyBox: {:?}", self);
+            println!("Dropping MyBox: {:?}", self.0);
+        }
+    }
+
+    #[test]
+    pub fn drop_order_scopes() {
+        drop_tracker::scope_example();
+    }
+
+    #[test]
+    pub fn drop_order_moves() {
+        drop_tracker::move_example();
+    }
+
+    //Test utility recording drops into a shared log so drop order can be
+    // asserted rather than read off stdout. The log is Arc<Mutex<_>> so
+    // tracked values may be moved into other threads.
+    pub mod drop_tracker {
+        use std::fmt;
+        use std::ops::{Deref, DerefMut};
+        use std::rc::Rc;
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+
+        #[derive(Clone, Default)]
+        pub struct DropTracker {
+            log: Arc<Mutex<Vec<String>>>,
+        }
+
+        impl DropTracker {
+            pub fn new() -> DropTracker {
+                DropTracker::default()
+            }
+
+            pub fn track<T>(&self, name: &str, value: T) -> Tracked<T> {
+                Tracked {
+                    name: name.to_string(),
+                    value,
+                    log: Arc::clone(&self.log),
+                }
+            }
+
+            //Names in the order they were dropped.
+            pub fn dropped(&self) -> Vec<String> {
+                self.log.lock().unwrap().clone()
+            }
+
+            //As dropped() but clears the log for the next assertion.
+            pub fn take(&self) -> Vec<String> {
+                std::mem::take(&mut *self.log.lock().unwrap())
+            }
+        }
+
+        pub struct Tracked<T> {
+            name: String,
+            value: T,
+            log: Arc<Mutex<Vec<String>>>,
+        }
+
+        impl<T> Tracked<T> {
+            pub fn name(&self) -> &str {
+                &self.name
+            }
+        }
+
+        impl<T> Deref for Tracked<T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                &self.value
+            }
+        }
+
+        impl<T> DerefMut for Tracked<T> {
+            fn deref_mut(&mut self) -> &mut T {
+                &mut self.value
+            }
+        }
+
+        impl<T: fmt::Debug> fmt::Debug for Tracked<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}:{:?}", self.name, self.value)
+            }
+        }
+
+        //The name is logged before the value is dropped, so a tracked value
+        // holding others is logged ahead of them.
+        impl<T> Drop for Tracked<T> {
+            fn drop(&mut self) {
+                //Still log if another tracked drop panicked with the lock.
+                let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+                log.push(self.name.clone());
+            }
+        }
+
+        struct Pair {
+            _first: Tracked<()>,
+            _second: Tracked<()>,
+        }
+
+        pub fn scope_example() {
+            let tracker = DropTracker::new();
+            {
+                let _a = tracker.track("a", ());
+                {
+                    let _b = tracker.track("b", ());
+                    let _c = tracker.track("c", ());
+                }
+                //Locals drop in reverse declaration order at scope end.
+                assert_eq!(tracker.take(), ["c", "b"]);
+                let _d = tracker.track("d", ());
+            }
+            assert_eq!(tracker.take(), ["d", "a"]);
+
+            //Struct fields and array elements drop front to back.
+            {
+                let _pair = Pair {
+                    _second: tracker.track("second", ()),
+                    _first: tracker.track("first", ()),
+                };
+                let _array = [tracker.track("v0", ()), tracker.track("v1", ())];
+            }
+            assert_eq!(tracker.take(), ["v0", "v1", "first", "second"]);
+
+            //Outer values are logged before the values they own.
+            {
+                let _outer = tracker.track("outer", tracker.track("inner", 1));
+            }
+            assert_eq!(tracker.take(), ["outer", "inner"]);
+
+            //A temporary is dropped at the end of its statement and a
+            // value bound to _ is never bound at all.
+            let _ = tracker.track("underscore", ());
+            assert_eq!(tracker.take(), ["underscore"]);
+
+            let mut x = tracker.track("x1", ());
+            assert_eq!(x.name(), "x1");
+            x = tracker.track("x2", ());
+            assert_eq!(tracker.take(), ["x1"]);
+            drop(x);
+            assert_eq!(tracker.take(), ["x2"]);
+        }
+
+        pub fn move_example() {
+            let tracker = DropTracker::new();
+
+            //std::mem::drop ends the value early, regardless of scope.
+            let a = tracker.track("a", ());
+            let b = tracker.track("b", ());
+            drop(a);
+            assert_eq!(tracker.dropped(), ["a"]);
+            drop(b);
+            assert_eq!(tracker.take(), ["a", "b"]);
+
+            //Moved into a thread, dropped when the closure finishes.
+            let sent = tracker.track("sent", vec![1, 2, 3]);
+            let kept = tracker.track("kept", ());
+            let handle = thread::spawn(move || sent.len());
+            assert_eq!(handle.join().unwrap(), 3);
+            assert_eq!(tracker.take(), ["sent"]);
+
+            //Returned from the thread, dropped back on this one.
+            let handle = {
+                let tracker = tracker.clone();
+                thread::spawn(move || tracker.track("returned", ()))
+            };
+            let returned = handle.join().unwrap();
+            assert!(tracker.dropped().is_empty());
+            drop(returned);
+            drop(kept);
+            assert_eq!(tracker.take(), ["returned", "kept"]);
+
+            //Rc drops the value with the last strong reference.
+            let shared = Rc::new(tracker.track("shared", ()));
+            let clone = Rc::clone(&shared);
+            let weak = Rc::downgrade(&shared);
+            drop(shared);
+            assert!(tracker.dropped().is_empty());
+            drop(clone);
+            assert_eq!(tracker.take(), ["shared"]);
+            assert!(weak.upgrade().is_none());
+
+            //super::LoggedBox prints first, then drops its contents.
+            let logged = super::LoggedBox::new(tracker.track("logged", ()));
+            drop(logged);
+            assert_eq!(tracker.take(), ["logged"]);
         }
     }
     //-Box Example------------------------------------------------------------/
@@ -496,26 +5037,314 @@ pub mod tests {
     //-Shared Ownership Example-----------------------------------------------/
     //Shared immutable ownership example use reference count Rc<T>.
     //The clone does not make a copy but increments reference count.
-    enum List {
-        Cons(i32, Rc<List>),
-        Nil,
+    //push_front() shares the existing list as the tail of the new one, so
+    // a list is never changed once built (a persistent list).
+    //The list is generated for a pointer type, Rc here and Arc in arc_list,
+    // so both share one implementation.
+    macro_rules! persistent_list {
+        ($ptr:ident) => {
+            #[derive(Default)]
+            pub enum List<T> {
+                Cons(T, $ptr<List<T>>),
+                #[default]
+                Nil,
+            }
+
+            impl<T> List<T> {
+                pub fn new() -> $ptr<List<T>> {
+                    $ptr::new(Nil)
+                }
+
+                //A self: &Rc<Self> (or &Arc<Self>) receiver lets the new list clone
+                // the pointer.
+                pub fn push_front(self: &$ptr<Self>, value: T) -> $ptr<List<T>> {
+                    $ptr::new(Cons(value, $ptr::clone(self)))
+                }
+
+                pub fn head(&self) -> Option<&T> {
+                    match self {
+                        Cons(value, _) => Some(value),
+                        Nil => None,
+                    }
+                }
+
+                pub fn tail(&self) -> Option<&$ptr<List<T>>> {
+                    match self {
+                        Cons(_, tail) => Some(tail),
+                        Nil => None,
+                    }
+                }
+
+                pub fn len(&self) -> usize {
+                    self.iter().count()
+                }
+
+                pub fn is_empty(&self) -> bool {
+                    matches!(self, Nil)
+                }
+
+                pub fn iter(&self) -> ListIter<'_, T> {
+                    ListIter { next: self }
+                }
+
+                pub fn fold<A, F>(&self, init: A, f: F) -> A
+                where
+                    F: FnMut(A, &T) -> A,
+                {
+                    self.iter().fold(init, f)
+                }
+
+                //New list in the same order, nothing is shared with self.
+                pub fn map<U, F>(&self, f: F) -> $ptr<List<U>>
+                where
+                    F: Fn(&T) -> U,
+                {
+                    let values: Vec<U> = self.iter().map(f).collect();
+                    values
+                        .into_iter()
+                        .rev()
+                        .fold(List::new(), |list, value| list.push_front(value))
+                }
+
+                pub fn reverse(&self) -> $ptr<List<T>>
+                where
+                    T: Clone,
+                {
+                    self.fold(List::new(), |list, value| list.push_front(value.clone()))
+                }
+            }
+
+            //The default drop recurses once per node and overflows the stack on a
+            // long list. Instead unlink the tail while this list is its only owner,
+            // swapping in a shared Nil so each unlinked node drops without recursing.
+            //Stops at the first node still shared with another list.
+            impl<T> Drop for List<T> {
+                fn drop(&mut self) {
+                    let tail = match self {
+                        Cons(_, tail) if $ptr::strong_count(tail) == 1 && !tail.is_empty() => tail,
+                        _ => return,
+                    };
+                    let nil = $ptr::new(Nil);
+                    let mut next = std::mem::replace(tail, $ptr::clone(&nil));
+                    while let Ok(mut node) = $ptr::try_unwrap(next) {
+                        match &mut node {
+                            Cons(_, tail) => next = std::mem::replace(tail, $ptr::clone(&nil)),
+                            Nil => break,
+                        }
+                    }
+                }
+            }
+
+            pub struct ListIter<'a, T> {
+                next: &'a List<T>,
+            }
+
+            impl<'a, T> Iterator for ListIter<'a, T> {
+                type Item = &'a T;
+
+                fn next(&mut self) -> Option<Self::Item> {
+                    match self.next {
+                        Cons(value, tail) => {
+                            self.next = tail;
+                            Some(value)
+                        }
+                        Nil => None,
+                    }
+                }
+            }
+
+            impl<'a, T> IntoIterator for &'a List<T> {
+                type Item = &'a T;
+                type IntoIter = ListIter<'a, T>;
+
+                fn into_iter(self) -> Self::IntoIter {
+                    self.iter()
+                }
+            }
+
+            //Values keep the iterator order, the first value is the head.
+            impl<T> FromIterator<T> for List<T> {
+                fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+                    let values: Vec<T> = iter.into_iter().collect();
+                    let mut list = Nil;
+                    for value in values.into_iter().rev() {
+                        list = Cons(value, $ptr::new(list));
+                    }
+                    list
+                }
+            }
+
+            //Compare values not pointers, iterating avoids deep recursion.
+            impl<T: PartialEq> PartialEq for List<T> {
+                fn eq(&self, other: &Self) -> bool {
+                    self.iter().eq(other.iter())
+                }
+            }
+
+            impl<T: Eq> Eq for List<T> {}
+
+            impl<T: std::fmt::Display> std::fmt::Display for List<T> {
+                fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    write!(f, "(")?;
+                    for (i, value) in self.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, " ")?;
+                        }
+                        write!(f, "{}", value)?;
+                    }
+                    write!(f, ")")
+                }
+            }
+
+            impl<T: std::fmt::Debug> std::fmt::Debug for List<T> {
+                fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.debug_list().entries(self.iter()).finish()
+                }
+            }
+        };
+    }
+
+    use crate::tests::List::{Cons, Nil};
+    use std::rc::Rc;
+
+    persistent_list!(Rc);
+
+    #[test]
+    pub fn shared_immutable_example() {
+        let a = Rc::new(Cons(5, Rc::new(Cons(10, Rc::new(Nil)))));
+        println!("count after creating a = {}", Rc::strong_count(&a));
+        let _b = Cons(3, Rc::clone(&a));
+        println!("count after creating b = {}", Rc::strong_count(&a));
+        {
+            let _c = Cons(4, Rc::clone(&a));
+            println!("count after creating c = {}", Rc::strong_count(&a));
+        }
+
+        println!("count after c goes out of scope = {}", Rc::strong_count(&a));
+    }
+
+    #[test]
+    pub fn persistent_list() {
+        let a = Rc::new(List::from_iter([5, 10]));
+        assert_eq!(Rc::strong_count(&a), 1);
+        let b = a.push_front(3);
+        assert_eq!(Rc::strong_count(&a), 2);
+        {
+            let c = a.push_front(4);
+            assert_eq!(Rc::strong_count(&a), 3);
+            assert!(Rc::ptr_eq(c.tail().unwrap(), &a));
+            assert_eq!(c.to_string(), "(4 5 10)");
+        }
+        assert_eq!(Rc::strong_count(&a), 2);
+
+        assert_eq!(b.head(), Some(&3));
+        assert_eq!(b.len(), 3);
+        assert_eq!(a.to_string(), "(5 10)");
+        assert_eq!(format!("{:?}", b), "[3, 5, 10]");
+        assert_eq!(b.iter().copied().collect::<Vec<_>>(), vec![3, 5, 10]);
+        assert_eq!(b.fold(0, |sum, v| sum + v), 18);
+        assert_eq!(b.reverse().to_string(), "(10 5 3)");
+        assert_eq!(b.map(|v| v * 2).to_string(), "(6 10 20)");
+        assert_eq!(List::<i32>::new().head(), None);
+        assert!(List::<i32>::new().is_empty());
+        assert_eq!(List::<i32>::new().to_string(), "()");
+
+        //Structural equality, hand built and collected lists match.
+        let built = Rc::new(Cons(3, Rc::new(Cons(5, Rc::new(Cons(10, Rc::new(Nil)))))));
+        assert_eq!(built, b);
+        assert_ne!(*built, *a);
+
+        //Dropping b releases its hold on the shared tail.
+        drop(b);
+        assert_eq!(Rc::strong_count(&a), 1);
+    }
+
+    #[test]
+    pub fn long_list_drop() {
+        let mut list = List::new();
+        for i in 0..3_000_000 {
+            list = list.push_front(i);
+        }
+        assert_eq!(list.len(), 3_000_000);
+        drop(list);
+
+        let list = Rc::new(List::from_iter(0..3_000_000));
+        drop(list);
+    }
+
+    #[test]
+    pub fn long_list_drop_keeps_shared_tail() {
+        let shared = Rc::new(List::from_iter(0..1_000_000));
+        let mut list = Rc::clone(&shared);
+        for i in 0..1_000_000 {
+            list = list.push_front(i);
+        }
+        assert_eq!(Rc::strong_count(&shared), 2);
+
+        drop(list);
+        assert_eq!(Rc::strong_count(&shared), 1);
+        assert_eq!(shared.len(), 1_000_000);
+        assert_eq!(shared.head(), Some(&0));
     }
 
-    use crate::tests::List::{Cons, Nil};
-    use std::rc::Rc;
+    //Arc<T> twin of List so a list can be shared between threads.
+    #[test]
+    pub fn arc_list_threads() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let base = Arc::new(arc_list::List::from_iter([5, 10]));
+        let mut handles = vec![];
+
+        for i in 0..10 {
+            let base = Arc::clone(&base);
+            let handle = thread::spawn(move || {
+                let list = base.push_front(i);
+                assert!(Arc::ptr_eq(list.tail().unwrap(), &base));
+                list
+            });
+            handles.push(handle);
+        }
 
-    #[test]
-    pub fn shared_immutable_example() {
-        let a = Rc::new(Cons(5, Rc::new(Cons(10, Rc::new(Nil)))));
-        println!("count after creating a = {}", Rc::strong_count(&a));
-        let _b = Cons(3, Rc::clone(&a));
-        println!("count after creating b = {}", Rc::strong_count(&a));
-        {
-            let _c = Cons(4, Rc::clone(&a));
-            println!("count after creating c = {}", Rc::strong_count(&a));
+        let lists: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        //base, plus one clone per thread list sharing it as a tail.
+        assert_eq!(Arc::strong_count(&base), 11);
+        for (i, list) in lists.iter().enumerate() {
+            assert_eq!(list.to_string(), format!("({} 5 10)", i));
         }
 
-        println!("count after c goes out of scope = {}", Rc::strong_count(&a));
+        drop(lists);
+        assert_eq!(Arc::strong_count(&base), 1);
+    }
+
+    #[test]
+    pub fn arc_list_api() {
+        use arc_list::List;
+        use std::sync::Arc;
+
+        let a = Arc::new(List::from_iter([5, 10]));
+        let b = a.push_front(3);
+        assert_eq!(Arc::strong_count(&a), 2);
+        assert_eq!(b.head(), Some(&3));
+        assert_eq!(b.len(), 3);
+        assert!(!b.is_empty());
+        assert_eq!(format!("{:?}", b), "[3, 5, 10]");
+        assert_eq!(b.fold(0, |sum, v| sum + v), 18);
+        assert_eq!(b.reverse().to_string(), "(10 5 3)");
+        assert_eq!(b.map(|v| v * 2).to_string(), "(6 10 20)");
+        assert_eq!(*b, List::from_iter([3, 5, 10]));
+        assert_eq!((&*b).into_iter().count(), 3);
+        assert_eq!(List::<i32>::new().head(), None);
+        assert_eq!(*List::<i32>::new(), List::default());
+    }
+
+    //Arc::try_unwrap in the iterative drop only succeeds for the last
+    // owner, so another thread holding a tail keeps it alive.
+    pub mod arc_list {
+        use std::sync::Arc;
+        use List::{Cons, Nil};
+
+        persistent_list!(Arc);
     }
     //-Simple Ownership Example-----------------------------------------------/
     #[test]
@@ -630,155 +5459,820 @@ pub mod tests {
         }
     }
 
-    // function with a parameter with the Summary trait.
-    // 'pub fn notify(item: &impl Summary) {' is the same as the
-    // Trait Bound Syntax:
-    pub fn notify<T: Summary>(item: &T) {
-        println!("Breaking news! {}", item.summarize());
+    // function with a parameter with the Summary trait.
+    // 'pub fn notify(item: &impl Summary) {' is the same as the
+    // Trait Bound Syntax:
+    pub fn notify<T: Summary>(item: &T) {
+        println!("Breaking news! {}", item.summarize());
+    }
+
+    //Multiple Trait Bounds:
+    //pub fn notify(item: &(impl Summary + Display)) {
+    //pub fn notify<T: Summary + Display>(item: &T) {
+
+    //Trait Bounds with where:
+    //fn some_function<T, U>(t: &T, u: &U) -> i32
+    //    where T: Display + Clone,
+    //          U: Clone + Debug
+    //{
+
+    //Trait return type:
+    //fn returns_summarizable() -> impl Summary {
+
+    //Trait for method implementation:
+    //struct Pair<T> {...
+    //impl<T: Display + PartialOrd> Pair<T> {...
+
+    //Use Trait objects(eg Box<dyn Draw> instead of Trait bounds
+    // to process different objects with a trait. A generic type
+    // parameter can only be substituted by one concrete type at a time.
+
+    // Impl a Trait on a type
+    pub struct Tweet {
+        pub username: String,
+        pub content: String,
+        pub reply: bool,
+        pub retweet: bool,
+    }
+
+    impl Summary for Tweet {
+        fn summarize(&self) -> String {
+            format!("{}: {}", self.username, self.content)
+        }
+    }
+    //-Struct Lifetime Example------------------------------------------------/
+    #[test]
+    pub fn lifetime_test() {
+        let mut owner = Owner(18);
+        owner.add_one();
+        owner.print();
+    }
+
+    struct Owner(i32);
+
+    impl Owner {
+        // Annotate lifetimes as in a standalone function.
+        fn add_one<'a>(&'a mut self) {
+            self.0 += 1;
+        }
+        fn print<'a>(&'a self) {
+            println!("`print`: {}", self.0);
+        }
+    }
+    //-String Lifetime Example------------------------------------------------/
+    //Just return the string not dead reference!
+    //fn invalid_output<'a>() -> &'a String { &String::from("foo") }
+    pub fn invalid_output() -> String {
+        String::from("foo")
+    }
+
+    #[test]
+    pub fn str_test() {
+        let mut arg1 = vec!["abc", "def"];
+        let arg2 = vec!["ghi", "jkl"];
+        let arg3 = str_add(&arg1, &arg2);
+        println!("{:?}", arg1);
+        println!("{:?}", arg2);
+        println!("{:?}", arg3);
+        arg1 = str_add(&arg1, &arg2);
+        println!("{:?}", arg1);
+    }
+
+    pub fn str_add<'a>(arg1: &Vec<&'a str>, arg2: &Vec<&'a str>) -> Vec<&'a str> {
+        let mut vec = Vec::new();
+        vec.extend_from_slice(arg1);
+        vec.extend_from_slice(arg2);
+        return vec;
+    }
+
+    #[test]
+    pub fn string_test() {
+        let mut a1: Vec<String> = vec![String::from("abc"), String::from("def")];
+        let a2: Vec<String> = vec![String::from("ghi"), String::from("jkl")];
+        a1 = string_add(&a1, &a2);
+        println!("{:?}", a1);
+    }
+    pub fn string_add(arg1: &Vec<String>, arg2: &Vec<String>) -> Vec<String> {
+        let mut vec = Vec::new();
+        vec.extend_from_slice(arg1);
+        vec.extend_from_slice(arg2);
+        return vec;
+    }
+    //-Closure Example--------------------------------------------------------/
+    #[test]
+    pub fn closure_ex() {
+        let simulated_user_specified_value = 30;
+        let simulated_random_number = 3;
+        generate_workout(simulated_user_specified_value, simulated_random_number);
+    }
+
+    //Note: Fn is a closure trait.
+    //      fn is a function pointer.
+    //      fn implements all closure traints: Fn, FnMut, and FnOnce
+    //Results are memoized per argument in a HashMap, the closure only runs
+    // for an argument not seen before. An EvictionPolicy decides which
+    // entries are dropped, the default Unbounded policy keeps everything.
+    struct Cacher<T, K, V, P = Unbounded>
+    where
+        T: Fn(&K) -> V,
+        K: Hash + Eq,
+        V: Clone,
+        P: EvictionPolicy<K>,
+    {
+        calculation: T,
+        values: HashMap<K, V>,
+        policy: P,
+        stats: CacheStats,
+    }
+
+    use log::debug;
+    use std::collections::{BTreeMap, HashMap, VecDeque};
+    use std::hash::Hash;
+    use std::time::{Duration, Instant};
+
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct CacheStats {
+        pub hits: u64,
+        pub misses: u64,
+        pub evictions: u64,
+    }
+
+    //The Cacher tells the policy about inserts, hits and removals.
+    //victim() is asked after every insert until it returns None, a key it
+    // returns is forgotten by the policy and removed from the cache.
+    //on_remove() is only called for entries the Cacher drops itself,
+    // ie when expired() returned true on lookup.
+    pub trait EvictionPolicy<K> {
+        fn on_insert(&mut self, key: &K);
+        fn on_hit(&mut self, _key: &K) {}
+        fn on_remove(&mut self, _key: &K) {}
+        fn expired(&self, _key: &K) -> bool {
+            false
+        }
+        fn victim(&mut self) -> Option<K>;
+    }
+
+    //Never evict.
+    pub struct Unbounded;
+
+    impl<K> EvictionPolicy<K> for Unbounded {
+        fn on_insert(&mut self, _key: &K) {}
+        fn victim(&mut self) -> Option<K> {
+            None
+        }
+    }
+
+    //Keep at most capacity entries, evict the oldest insert first.
+    pub struct MaxSize<K> {
+        capacity: usize,
+        order: VecDeque<K>,
+    }
+
+    impl<K> MaxSize<K> {
+        pub fn new(capacity: usize) -> MaxSize<K> {
+            MaxSize {
+                capacity,
+                order: VecDeque::new(),
+            }
+        }
+    }
+
+    impl<K: Clone> EvictionPolicy<K> for MaxSize<K> {
+        fn on_insert(&mut self, key: &K) {
+            self.order.push_back(key.clone());
+        }
+        fn victim(&mut self) -> Option<K> {
+            if self.order.len() > self.capacity {
+                self.order.pop_front()
+            } else {
+                None
+            }
+        }
+    }
+
+    //Keep at most capacity entries, evict the least recently used first.
+    //Each use stamps the key with a rising tick, ordered in a BTreeMap, so a
+    // hit costs O(log n) rather than a scan of every cached key.
+    pub struct Lru<K> {
+        capacity: usize,
+        tick: u64,
+        ticks: HashMap<K, u64>,
+        order: BTreeMap<u64, K>,
+    }
+
+    impl<K> Lru<K> {
+        pub fn new(capacity: usize) -> Lru<K> {
+            Lru {
+                capacity,
+                tick: 0,
+                ticks: HashMap::new(),
+                order: BTreeMap::new(),
+            }
+        }
+    }
+
+    impl<K: Hash + Eq + Clone> Lru<K> {
+        fn touch(&mut self, key: &K) {
+            self.tick += 1;
+            if let Some(old) = self.ticks.insert(key.clone(), self.tick) {
+                self.order.remove(&old);
+            }
+            self.order.insert(self.tick, key.clone());
+        }
+    }
+
+    impl<K: Hash + Eq + Clone> EvictionPolicy<K> for Lru<K> {
+        fn on_insert(&mut self, key: &K) {
+            self.touch(key);
+        }
+        fn on_hit(&mut self, key: &K) {
+            self.touch(key);
+        }
+        fn on_remove(&mut self, key: &K) {
+            if let Some(tick) = self.ticks.remove(key) {
+                self.order.remove(&tick);
+            }
+        }
+        fn victim(&mut self) -> Option<K> {
+            if self.ticks.len() > self.capacity {
+                let (_, key) = self.order.pop_first()?;
+                self.ticks.remove(&key);
+                Some(key)
+            } else {
+                None
+            }
+        }
+    }
+
+    //Entries expire ttl after they were calculated. Expired entries are
+    // recalculated on lookup and swept from the front of the insert order
+    // on every insert so unused keys do not pile up.
+    //Time comes from a Clock, the system clock unless with_clock() is used.
+    pub struct Ttl<K, C: Clock = SystemClock> {
+        ttl: Duration,
+        clock: C,
+        inserted: HashMap<K, Instant>,
+        order: VecDeque<K>,
+    }
+
+    impl<K> Ttl<K> {
+        pub fn new(ttl: Duration) -> Ttl<K> {
+            Ttl::with_clock(ttl, SystemClock)
+        }
+    }
+
+    impl<K, C: Clock> Ttl<K, C> {
+        pub fn with_clock(ttl: Duration, clock: C) -> Ttl<K, C> {
+            Ttl {
+                ttl,
+                clock,
+                inserted: HashMap::new(),
+                order: VecDeque::new(),
+            }
+        }
+    }
+
+    impl<K: Hash + Eq + Clone, C: Clock> EvictionPolicy<K> for Ttl<K, C> {
+        fn on_insert(&mut self, key: &K) {
+            self.inserted.insert(key.clone(), self.clock.now());
+            self.order.push_back(key.clone());
+        }
+        fn on_remove(&mut self, key: &K) {
+            self.inserted.remove(key);
+            self.order.retain(|k| k != key);
+        }
+        fn expired(&self, key: &K) -> bool {
+            match self.inserted.get(key) {
+                Some(at) => self.clock.now().duration_since(*at) >= self.ttl,
+                None => false,
+            }
+        }
+        fn victim(&mut self) -> Option<K> {
+            let front = self.order.front()?;
+            if self.expired(front) {
+                let key = self.order.pop_front().unwrap();
+                self.inserted.remove(&key);
+                Some(key)
+            } else {
+                None
+            }
+        }
+    }
+
+    impl<T, K, V> Cacher<T, K, V>
+    where
+        T: Fn(&K) -> V,
+        K: Hash + Eq,
+        V: Clone,
+    {
+        fn new(calculation: T) -> Cacher<T, K, V> {
+            Cacher::with_policy(calculation, Unbounded)
+        }
+    }
+
+    impl<T, K, V, P> Cacher<T, K, V, P>
+    where
+        T: Fn(&K) -> V,
+        K: Hash + Eq,
+        V: Clone,
+        P: EvictionPolicy<K>,
+    {
+        fn with_policy(calculation: T, policy: P) -> Cacher<T, K, V, P> {
+            Cacher {
+                calculation,
+                values: HashMap::new(),
+                policy,
+                stats: CacheStats::default(),
+            }
+        }
+
+        fn value(&mut self, arg: K) -> V {
+            if self.policy.expired(&arg) && self.values.remove(&arg).is_some() {
+                self.policy.on_remove(&arg);
+                self.evicted();
+            }
+
+            if let Some(v) = self.values.get(&arg) {
+                self.stats.hits += 1;
+                self.policy.on_hit(&arg);
+                debug!("Cacher hit: {:?}", self.stats);
+                return v.clone();
+            }
+
+            self.stats.misses += 1;
+            debug!("Cacher miss: {:?}", self.stats);
+            let v = (self.calculation)(&arg);
+            self.policy.on_insert(&arg);
+            self.values.insert(arg, v.clone());
+
+            while let Some(key) = self.policy.victim() {
+                if self.values.remove(&key).is_some() {
+                    self.evicted();
+                }
+            }
+            v
+        }
+
+        fn evicted(&mut self) {
+            self.stats.evictions += 1;
+            debug!("Cacher eviction: {:?}", self.stats);
+        }
+
+        fn stats(&self) -> CacheStats {
+            self.stats
+        }
+
+        fn len(&self) -> usize {
+            self.values.len()
+        }
+    }
+
+    //Snapshot file, one entry per line with tab separated key and value:
+    //  rustex-cacher 1
+    //  entries 2
+    //  1\t1
+    //  2\t2
+    //Tabs, newlines and backslashes in keys and values are escaped.
+    //A file with the wrong version, a bad line or the wrong number of
+    // entries is ignored with a warning and the cache starts empty.
+    const SNAPSHOT_HEADER: &str = "rustex-cacher";
+    const SNAPSHOT_VERSION: u32 = 1;
+
+    use log::warn;
+    use std::fmt::Display;
+    use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+    use std::path::Path;
+    use std::str::FromStr;
+
+    impl<T, K, V, P> Cacher<T, K, V, P>
+    where
+        T: Fn(&K) -> V,
+        K: Hash + Eq + Display + FromStr,
+        V: Clone + Display + FromStr,
+        P: EvictionPolicy<K>,
+    {
+        //Warm the cache from a snapshot written by save().
+        //Loaded entries are inserted as if just calculated, the snapshot
+        // keeps no ages, so a Ttl restarts for every entry on each load.
+        // Entries the policy rejects are counted as evictions.
+        fn load(mut self, path: &Path) -> Self {
+            match read_snapshot(path) {
+                Ok(entries) => {
+                    for (key, value) in entries {
+                        self.policy.on_insert(&key);
+                        self.values.insert(key, value);
+                    }
+                    while let Some(key) = self.policy.victim() {
+                        if self.values.remove(&key).is_some() {
+                            self.evicted();
+                        }
+                    }
+                    debug!("Cacher loaded {} entries from {:?}", self.len(), path);
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    debug!("Cacher snapshot {:?} not found", path);
+                }
+                Err(e) => warn!("Cacher snapshot {:?} ignored: {}", path, e),
+            }
+            self
+        }
+
+        //Write to a temporary file then rename so a crash never leaves a
+        // half written snapshot behind.
+        fn save(&self, path: &Path) -> std::io::Result<()> {
+            let tmp = path.with_extension("tmp");
+            {
+                let mut out = BufWriter::new(std::fs::File::create(&tmp)?);
+                writeln!(out, "{} {}", SNAPSHOT_HEADER, SNAPSHOT_VERSION)?;
+                writeln!(out, "entries {}", self.values.len())?;
+                for (key, value) in &self.values {
+                    let key = escape(&key.to_string());
+                    let value = escape(&value.to_string());
+                    writeln!(out, "{}\t{}", key, value)?;
+                }
+                out.flush()?;
+            }
+            std::fs::rename(&tmp, path)
+        }
+    }
+
+    fn read_snapshot<K: FromStr, V: FromStr>(path: &Path) -> std::io::Result<Vec<(K, V)>> {
+        let invalid = |msg: String| std::io::Error::new(ErrorKind::InvalidData, msg);
+        let mut lines = BufReader::new(std::fs::File::open(path)?).lines();
+
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let expected = format!("{} {}", SNAPSHOT_HEADER, SNAPSHOT_VERSION);
+        if header != expected {
+            return Err(invalid(format!("unknown header {:?}", header)));
+        }
+
+        let count = lines.next().transpose()?.unwrap_or_default();
+        let count: usize = match count.strip_prefix("entries ").map(str::parse) {
+            Some(Ok(n)) => n,
+            _ => return Err(invalid(format!("bad entry count {:?}", count))),
+        };
+
+        let mut entries = Vec::with_capacity(count);
+        for (n, line) in lines.enumerate() {
+            let line = line?;
+            let entry = line.split_once('\t').and_then(|(k, v)| {
+                let k = unescape(k)?.parse().ok()?;
+                let v = unescape(v)?.parse().ok()?;
+                Some((k, v))
+            });
+            match entry {
+                Some(entry) => entries.push(entry),
+                None => return Err(invalid(format!("bad entry on line {}", n + 3))),
+            }
+        }
+
+        if entries.len() != count {
+            return Err(invalid(format!(
+                "expected {} entries, found {}",
+                count,
+                entries.len()
+            )));
+        }
+        Ok(entries)
+    }
+
+    fn escape(s: &str) -> String {
+        let mut out = String::with_capacity(s.len());
+        for c in s.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '\t' => out.push_str("\\t"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                c => out.push(c),
+            }
+        }
+        out
+    }
+
+    fn unescape(s: &str) -> Option<String> {
+        let mut out = String::with_capacity(s.len());
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            match chars.next()? {
+                '\\' => out.push('\\'),
+                't' => out.push('\t'),
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                _ => return None,
+            }
+        }
+        Some(out)
     }
 
-    //Multiple Trait Bounds:
-    //pub fn notify(item: &(impl Summary + Display)) {
-    //pub fn notify<T: Summary + Display>(item: &T) {
+    #[test]
+    pub fn cacher_distinct_args() {
+        use std::cell::Cell;
 
-    //Trait Bounds with where:
-    //fn some_function<T, U>(t: &T, u: &U) -> i32
-    //    where T: Display + Clone,
-    //          U: Clone + Debug
-    //{
+        let calls = Cell::new(0);
+        let mut c = Cacher::new(|a: &u32| {
+            calls.set(calls.get() + 1);
+            *a
+        });
 
-    //Trait return type:
-    //fn returns_summarizable() -> impl Summary {
+        assert_eq!(c.value(1), 1);
+        assert_eq!(c.value(2), 2);
+        assert_eq!(c.value(1), 1);
+        assert_eq!(c.value(2), 2);
+        assert_eq!(calls.get(), 2);
+    }
 
-    //Trait for method implementation:
-    //struct Pair<T> {...
-    //impl<T: Display + PartialOrd> Pair<T> {...
+    #[test]
+    pub fn cacher_generic_types() {
+        let mut c = Cacher::new(|s: &&str| s.len());
+        assert_eq!(c.value("abc"), 3);
+        assert_eq!(c.value("abcdef"), 6);
 
-    //Use Trait objects(eg Box<dyn Draw> instead of Trait bounds
-    // to process different objects with a trait. A generic type
-    // parameter can only be substituted by one concrete type at a time.
+        let mut c = Cacher::new(|n: &u64| format!("n={}", n));
+        assert_eq!(c.value(7), "n=7");
+        assert_eq!(c.value(8), "n=8");
+        assert_eq!(c.len(), 2);
+    }
 
-    // Impl a Trait on a type
-    pub struct Tweet {
-        pub username: String,
-        pub content: String,
-        pub reply: bool,
-        pub retweet: bool,
+    #[test]
+    pub fn cacher_lru() {
+        let mut c = Cacher::with_policy(|n: &u32| n * 2, Lru::new(2));
+        c.value(1);
+        c.value(2);
+        c.value(1); //2 is now least recently used
+        c.value(3);
+        assert_eq!(c.len(), 2);
+        assert!(c.values.contains_key(&1));
+        assert!(!c.values.contains_key(&2));
+        c.value(1);
+        c.value(3); //repeated hits keep a single entry per key
+        c.value(4);
+        assert!(c.values.contains_key(&3));
+        assert!(!c.values.contains_key(&1));
+        assert_eq!(
+            c.stats(),
+            CacheStats {
+                hits: 3,
+                misses: 4,
+                evictions: 2
+            }
+        );
     }
 
-    impl Summary for Tweet {
-        fn summarize(&self) -> String {
-            format!("{}: {}", self.username, self.content)
-        }
+    #[test]
+    pub fn cacher_max_size() {
+        let mut c = Cacher::with_policy(|n: &u32| n * 2, MaxSize::new(2));
+        c.value(1);
+        c.value(2);
+        c.value(1); //hits do not matter, 1 is still the oldest
+        c.value(3);
+        assert!(!c.values.contains_key(&1));
+        assert!(c.values.contains_key(&2));
+        assert_eq!(c.stats().evictions, 1);
     }
-    //-Struct Lifetime Example------------------------------------------------/
+
     #[test]
-    pub fn lifetime_test() {
-        let mut owner = Owner(18);
-        owner.add_one();
-        owner.print();
+    pub fn cacher_ttl() {
+        use std::cell::Cell;
+
+        let calls = Cell::new(0);
+        let clock = ManualClock::new();
+        let mut c = Cacher::with_policy(
+            |n: &u32| {
+                calls.set(calls.get() + 1);
+                *n
+            },
+            Ttl::with_clock(Duration::from_millis(50), &clock),
+        );
+        c.value(1);
+        clock.advance(Duration::from_millis(49));
+        c.value(2);
+        c.value(1);
+        assert_eq!(calls.get(), 2);
+
+        clock.advance(Duration::from_millis(50));
+        //1 has expired so it is calculated again, 2 is swept on insert.
+        c.value(1);
+        assert_eq!(calls.get(), 3);
+        assert_eq!(c.len(), 1);
+        assert_eq!(
+            c.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 3,
+                evictions: 2
+            }
+        );
     }
 
-    struct Owner(i32);
+    //A custom policy that only keeps even keys.
+    struct EvenOnly(Option<u32>);
 
-    impl Owner {
-        // Annotate lifetimes as in a standalone function.
-        fn add_one<'a>(&'a mut self) {
-            self.0 += 1;
+    impl EvictionPolicy<u32> for EvenOnly {
+        fn on_insert(&mut self, key: &u32) {
+            if key % 2 == 1 {
+                self.0 = Some(*key);
+            }
         }
-        fn print<'a>(&'a self) {
-            println!("`print`: {}", self.0);
+        fn victim(&mut self) -> Option<u32> {
+            self.0.take()
         }
     }
-    //-String Lifetime Example------------------------------------------------/
-    //Just return the string not dead reference!
-    //fn invalid_output<'a>() -> &'a String { &String::from("foo") }
-    pub fn invalid_output() -> String {
-        String::from("foo")
-    }
 
     #[test]
-    pub fn str_test() {
-        let mut arg1 = vec!["abc", "def"];
-        let arg2 = vec!["ghi", "jkl"];
-        let arg3 = str_add(&arg1, &arg2);
-        println!("{:?}", arg1);
-        println!("{:?}", arg2);
-        println!("{:?}", arg3);
-        arg1 = str_add(&arg1, &arg2);
-        println!("{:?}", arg1);
+    pub fn cacher_custom_policy() {
+        let mut c = Cacher::with_policy(|n: &u32| n + 1, EvenOnly(None));
+        for n in 0..6 {
+            assert_eq!(c.value(n), n + 1);
+        }
+        assert_eq!(c.len(), 3);
+        assert_eq!(c.stats().evictions, 3);
     }
 
-    pub fn str_add<'a>(arg1: &Vec<&'a str>, arg2: &Vec<&'a str>) -> Vec<&'a str> {
-        let mut vec = Vec::new();
-        vec.extend_from_slice(arg1);
-        vec.extend_from_slice(arg2);
-        return vec;
+    fn snapshot_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rustex-{}-{}.cache", name, std::process::id()))
     }
 
     #[test]
-    pub fn string_test() {
-        let mut a1: Vec<String> = vec![String::from("abc"), String::from("def")];
-        let a2: Vec<String> = vec![String::from("ghi"), String::from("jkl")];
-        a1 = string_add(&a1, &a2);
-        println!("{:?}", a1);
+    pub fn cacher_snapshot_round_trip() {
+        use std::cell::Cell;
+
+        let path = snapshot_path("round-trip");
+        let mut c = Cacher::new(|s: &String| format!("{}\t{}\n\\", s, s.len()));
+        c.value(String::from("a"));
+        c.value(String::from("tab\there"));
+        c.save(&path).unwrap();
+
+        let calls = Cell::new(0);
+        let mut c = Cacher::new(|s: &String| {
+            calls.set(calls.get() + 1);
+            s.clone()
+        })
+        .load(&path);
+        assert_eq!(c.len(), 2);
+        assert_eq!(c.value(String::from("a")), "a\t1\n\\");
+        assert_eq!(c.value(String::from("tab\there")), "tab\there\t8\n\\");
+        assert_eq!(calls.get(), 0);
+        std::fs::remove_file(&path).unwrap();
     }
-    pub fn string_add(arg1: &Vec<String>, arg2: &Vec<String>) -> Vec<String> {
-        let mut vec = Vec::new();
-        vec.extend_from_slice(arg1);
-        vec.extend_from_slice(arg2);
-        return vec;
+
+    #[test]
+    pub fn cacher_snapshot_respects_policy() {
+        let path = snapshot_path("policy");
+        let mut c = Cacher::new(|n: &u32| n * 2);
+        for n in 0..5 {
+            c.value(n);
+        }
+        c.save(&path).unwrap();
+
+        let c = Cacher::with_policy(|n: &u32| n * 2, Lru::new(3)).load(&path);
+        assert_eq!(c.len(), 3);
+        assert_eq!(c.stats().evictions, 2);
+
+        //The snapshot keeps no ages, a Ttl starts again from the load.
+        let mut c =
+            Cacher::with_policy(|n: &u32| n * 2, Ttl::new(Duration::from_secs(60))).load(&path);
+        assert_eq!(c.len(), 5);
+        c.value(0);
+        assert_eq!(c.stats().hits, 1);
+        std::fs::remove_file(&path).unwrap();
     }
-    //-Closure Example--------------------------------------------------------/
+
     #[test]
-    pub fn closure_ex() {
-        let simulated_user_specified_value = 30;
-        let simulated_random_number = 3;
-        generate_workout(simulated_user_specified_value, simulated_random_number);
+    pub fn cacher_snapshot_rejects_bad_files() {
+        let path = snapshot_path("bad");
+        let bad = [
+            "rustex-cacher 2\nentries 1\n1\t2\n",
+            "rustex-cacher 1\nentries 2\n1\t2\n",
+            "rustex-cacher 1\nentries 1\n1 2\n",
+            "rustex-cacher 1\nentries 1\nx\t2\n",
+            "rustex-cacher 1\nentries 1\n1\t\\q\n",
+            "",
+        ];
+        for contents in bad {
+            std::fs::write(&path, contents).unwrap();
+            let c = Cacher::new(|n: &u32| *n).load(&path);
+            assert_eq!(c.len(), 0, "loaded {:?}", contents);
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let c = Cacher::new(|n: &u32| *n).load(&path);
+        assert_eq!(c.len(), 0);
     }
 
-    //Note: Fn is a closure trait.
-    //      fn is a function pointer.
-    //      fn implements all closure traints: Fn, FnMut, and FnOnce
-    struct Cacher<T>
+    //Thread safe Cacher, share between threads with Arc<SyncCacher<..>>.
+    //Each key has its own Arc<Mutex<Option<V>>> slot. The map lock is only
+    // held to find the slot, the slot lock is held while calculating so
+    // callers asking for the same key wait for the single calculation.
+    pub struct SyncCacher<T, K, V>
     where
-        T: Fn(u32) -> u32,
+        T: Fn(&K) -> V,
+        K: Hash + Eq,
+        V: Clone,
     {
         calculation: T,
-        value: Option<u32>,
+        values: Mutex<HashMap<K, Arc<Mutex<Option<V>>>>>,
     }
 
-    impl<T> Cacher<T>
+    use std::sync::{Arc, Mutex};
+
+    impl<T, K, V> SyncCacher<T, K, V>
     where
-        T: Fn(u32) -> u32,
+        T: Fn(&K) -> V,
+        K: Hash + Eq + Clone,
+        V: Clone,
     {
-        fn new(calculation: T) -> Cacher<T> {
-            Cacher {
+        pub fn new(calculation: T) -> SyncCacher<T, K, V> {
+            SyncCacher {
                 calculation,
-                value: None,
+                values: Mutex::new(HashMap::new()),
             }
         }
 
-        fn value(&mut self, arg: u32) -> u32 {
-            match self.value {
-                Some(v) => v,
+        pub fn value(&self, arg: K) -> V {
+            let slot = {
+                let mut values = self.values.lock().unwrap();
+                Arc::clone(values.entry(arg.clone()).or_default())
+            };
+
+            //A panic in the calculation poisons the slot but leaves it None,
+            // so the next caller just calculates again.
+            let mut slot = slot.lock().unwrap_or_else(|e| e.into_inner());
+            match &*slot {
+                Some(v) => v.clone(),
                 None => {
-                    let v = (self.calculation)(arg);
-                    self.value = Some(v);
+                    let v = (self.calculation)(&arg);
+                    *slot = Some(v.clone());
                     v
                 }
             }
         }
     }
 
+    #[test]
+    pub fn sync_cacher_single_flight() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::thread;
+        use std::time::Duration;
+
+        let calls: Arc<Vec<AtomicUsize>> = Arc::new((0..4).map(|_| AtomicUsize::new(0)).collect());
+        let counter = Arc::clone(&calls);
+        let cacher = Arc::new(SyncCacher::new(move |key: &usize| {
+            counter[*key].fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(50));
+            key * 10
+        }));
+        let mut handles = vec![];
+
+        for i in 0..40 {
+            let cacher = Arc::clone(&cacher);
+            let handle = thread::spawn(move || {
+                let key = i % 4;
+                assert_eq!(cacher.value(key), key * 10);
+            });
+            handles.push(handle);
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        for count in calls.iter() {
+            assert_eq!(count.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    pub fn sync_cacher_recovers_from_panic() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::thread;
+
+        let fail = Arc::new(AtomicBool::new(true));
+        let should_fail = Arc::clone(&fail);
+        let cacher = Arc::new(SyncCacher::new(move |key: &u32| {
+            if should_fail.swap(false, Ordering::SeqCst) {
+                panic!("calculation failed");
+            }
+            key + 1
+        }));
+
+        let c = Arc::clone(&cacher);
+        assert!(thread::spawn(move || c.value(1)).join().is_err());
+        assert_eq!(cacher.value(1), 2);
+    }
+
     fn generate_workout(intensity: u32, random_number: u32) {
         use std::thread;
         use std::time::Duration;
 
-        let mut expensive_result = Cacher::new(|num| {
+        let mut expensive_result = Cacher::new(|num: &u32| {
             println!("calculating slowly...");
             thread::sleep(Duration::from_secs(2));
-            num
+            *num
         });
 
         if intensity < 25 {
@@ -941,6 +6435,368 @@ pub mod tests {
             println!("{}", *r2);
         }
     }
+
+    #[test]
+    pub fn my_rc_shared() {
+        my_rc::shared_example();
+    }
+
+    #[test]
+    pub fn my_rc_weak() {
+        my_rc::weak_example();
+    }
+
+    #[test]
+    pub fn my_rc_drop() {
+        my_rc::drop_example();
+    }
+
+    //Rc<T> and Weak<T> written out with raw pointers. The counts live in the
+    // heap block next to the value, like std: the strong references together
+    // hold one weak reference, so the block is freed when the weak count
+    // reaches zero and the value is dropped when the strong count does.
+    pub mod my_rc {
+        use std::cell::{Cell, RefCell};
+        use std::marker::PhantomData;
+        use std::mem::ManuallyDrop;
+        use std::ops::Deref;
+        use std::ptr::NonNull;
+        use std::rc::{Rc, Weak};
+
+        struct RcBox<T> {
+            strong: Cell<usize>,
+            //Includes the one held by all the strong references.
+            weak: Cell<usize>,
+            value: ManuallyDrop<T>,
+        }
+
+        //NonNull is neither Send nor Sync, so neither is MyRc, as Rc.
+        pub struct MyRc<T> {
+            ptr: NonNull<RcBox<T>>,
+            //Tells drop check a MyRc owns a T.
+            owns: PhantomData<RcBox<T>>,
+        }
+
+        //None for MyWeak::new(), which has no allocation to point to.
+        pub struct MyWeak<T> {
+            ptr: Option<NonNull<RcBox<T>>>,
+        }
+
+        impl<T> MyRc<T> {
+            pub fn new(value: T) -> MyRc<T> {
+                let rc_box = Box::new(RcBox {
+                    strong: Cell::new(1),
+                    weak: Cell::new(1),
+                    value: ManuallyDrop::new(value),
+                });
+                MyRc {
+                    ptr: NonNull::from(Box::leak(rc_box)),
+                    owns: PhantomData,
+                }
+            }
+
+            fn inner(&self) -> &RcBox<T> {
+                //Safety: the block is freed only once the strong references,
+                // self among them, have all gone.
+                unsafe { self.ptr.as_ref() }
+            }
+
+            pub fn strong_count(this: &Self) -> usize {
+                this.inner().strong.get()
+            }
+
+            pub fn weak_count(this: &Self) -> usize {
+                this.inner().weak.get() - 1
+            }
+
+            pub fn downgrade(this: &Self) -> MyWeak<T> {
+                let inner = this.inner();
+                inner.weak.set(inner.weak.get() + 1);
+                MyWeak {
+                    ptr: Some(this.ptr),
+                }
+            }
+
+            //Some only when no other MyRc or MyWeak could see the change.
+            pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+                if Self::strong_count(this) == 1 && Self::weak_count(this) == 0 {
+                    //Safety: this is the only handle and it is borrowed mutably.
+                    Some(unsafe { &mut this.ptr.as_mut().value })
+                } else {
+                    None
+                }
+            }
+
+            pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+                this.ptr == other.ptr
+            }
+        }
+
+        impl<T> Clone for MyRc<T> {
+            fn clone(&self) -> MyRc<T> {
+                let inner = self.inner();
+                inner.strong.set(inner.strong.get() + 1);
+                MyRc {
+                    ptr: self.ptr,
+                    owns: PhantomData,
+                }
+            }
+        }
+
+        impl<T> Deref for MyRc<T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                &self.inner().value
+            }
+        }
+
+        impl<T> Drop for MyRc<T> {
+            fn drop(&mut self) {
+                let ptr = self.ptr.as_ptr();
+                //Safety: ptr is valid until the last weak reference, the one
+                // held by the strong references at the latest, is released.
+                // The value is dropped once, by the last strong reference.
+                unsafe {
+                    let strong = (*ptr).strong.get() - 1;
+                    (*ptr).strong.set(strong);
+                    if strong > 0 {
+                        return;
+                    }
+                    ManuallyDrop::drop(&mut (*ptr).value);
+                    release_weak(ptr);
+                }
+            }
+        }
+
+        //Safety: ptr must come from MyRc::new and hold a weak reference that
+        // the caller gives up here.
+        unsafe fn release_weak<T>(ptr: *mut RcBox<T>) {
+            let weak = (*ptr).weak.get() - 1;
+            (*ptr).weak.set(weak);
+            if weak == 0 {
+                //ManuallyDrop stops the value being dropped a second time.
+                drop(Box::from_raw(ptr));
+            }
+        }
+
+        impl<T> MyWeak<T> {
+            pub fn new() -> MyWeak<T> {
+                MyWeak { ptr: None }
+            }
+
+            fn inner(&self) -> Option<&RcBox<T>> {
+                //Safety: this weak reference keeps the block allocated.
+                self.ptr.map(|ptr| unsafe { &*ptr.as_ptr() })
+            }
+
+            pub fn upgrade(&self) -> Option<MyRc<T>> {
+                let inner = self.inner()?;
+                let strong = inner.strong.get();
+                if strong == 0 {
+                    return None;
+                }
+                inner.strong.set(strong + 1);
+                self.ptr.map(|ptr| MyRc {
+                    ptr,
+                    owns: PhantomData,
+                })
+            }
+
+            pub fn strong_count(&self) -> usize {
+                self.inner().map_or(0, |inner| inner.strong.get())
+            }
+
+            //As std, zero once no strong references remain.
+            pub fn weak_count(&self) -> usize {
+                match self.inner() {
+                    Some(inner) if inner.strong.get() > 0 => inner.weak.get() - 1,
+                    _ => 0,
+                }
+            }
+        }
+
+        impl<T> Default for MyWeak<T> {
+            fn default() -> Self {
+                MyWeak::new()
+            }
+        }
+
+        impl<T> Clone for MyWeak<T> {
+            fn clone(&self) -> MyWeak<T> {
+                if let Some(inner) = self.inner() {
+                    inner.weak.set(inner.weak.get() + 1);
+                }
+                MyWeak { ptr: self.ptr }
+            }
+        }
+
+        impl<T> Drop for MyWeak<T> {
+            fn drop(&mut self) {
+                if let Some(ptr) = self.ptr {
+                    //Safety: this weak reference is given up here.
+                    unsafe { release_weak(ptr.as_ptr()) }
+                }
+            }
+        }
+
+        //Assert the strong and weak counts agree with std::rc::Rc.
+        macro_rules! assert_counts {
+            ($mine:expr, $std:expr, $strong:expr, $weak:expr) => {
+                assert_eq!(
+                    (MyRc::strong_count($mine), MyRc::weak_count($mine)),
+                    (Rc::strong_count($std), Rc::weak_count($std))
+                );
+                assert_eq!(
+                    (Rc::strong_count($std), Rc::weak_count($std)),
+                    ($strong, $weak)
+                );
+            };
+        }
+
+        enum MyList {
+            Cons(i32, MyRc<MyList>),
+            Nil,
+        }
+
+        //shared_immutable_example on both, step by step.
+        pub fn shared_example() {
+            use super::List::{Cons as StdCons, Nil as StdNil};
+            use MyList::{Cons, Nil};
+
+            let a = MyRc::new(Cons(5, MyRc::new(Cons(10, MyRc::new(Nil)))));
+            let std_a = Rc::new(StdCons(5, Rc::new(StdCons(10, Rc::new(StdNil)))));
+            assert_counts!(&a, &std_a, 1, 0);
+            let _b = Cons(3, MyRc::clone(&a));
+            let _std_b = StdCons(3, Rc::clone(&std_a));
+            assert_counts!(&a, &std_a, 2, 0);
+            {
+                let _c = Cons(4, MyRc::clone(&a));
+                let _std_c = StdCons(4, Rc::clone(&std_a));
+                assert_counts!(&a, &std_a, 3, 0);
+            }
+            assert_counts!(&a, &std_a, 2, 0);
+
+            if let Cons(value, tail) = &*a {
+                assert_eq!(*value, 5);
+                assert!(matches!(**tail, Cons(10, _)));
+            }
+
+            let mut x = MyRc::new(1);
+            let mut std_x = Rc::new(1);
+            *MyRc::get_mut(&mut x).unwrap() += 1;
+            *Rc::get_mut(&mut std_x).unwrap() += 1;
+            let y = MyRc::clone(&x);
+            let std_y = Rc::clone(&std_x);
+            assert!(MyRc::get_mut(&mut x).is_none() && Rc::get_mut(&mut std_x).is_none());
+            assert!(MyRc::ptr_eq(&x, &y) && Rc::ptr_eq(&std_x, &std_y));
+            drop((y, std_y));
+            let weak = MyRc::downgrade(&x);
+            let std_weak = Rc::downgrade(&std_x);
+            assert!(MyRc::get_mut(&mut x).is_none() && Rc::get_mut(&mut std_x).is_none());
+            drop((weak, std_weak));
+            assert_eq!(MyRc::get_mut(&mut x), Rc::get_mut(&mut std_x));
+            assert_eq!(*x, 2);
+        }
+
+        struct Node {
+            value: i32,
+            parent: RefCell<MyWeak<Node>>,
+            children: RefCell<Vec<MyRc<Node>>>,
+        }
+
+        struct StdNode {
+            parent: RefCell<Weak<StdNode>>,
+            children: RefCell<Vec<Rc<StdNode>>>,
+        }
+
+        //weak_ex on both, step by step.
+        pub fn weak_example() {
+            let leaf = MyRc::new(Node {
+                value: 3,
+                parent: RefCell::new(MyWeak::new()),
+                children: RefCell::new(vec![]),
+            });
+            let std_leaf = Rc::new(StdNode {
+                parent: RefCell::new(Weak::new()),
+                children: RefCell::new(vec![]),
+            });
+            assert_counts!(&leaf, &std_leaf, 1, 0);
+            assert!(leaf.parent.borrow().upgrade().is_none());
+            assert!(std_leaf.parent.borrow().upgrade().is_none());
+            assert_eq!(leaf.parent.borrow().strong_count(), 0);
+
+            {
+                let branch = MyRc::new(Node {
+                    value: 5,
+                    parent: RefCell::new(MyWeak::new()),
+                    children: RefCell::new(vec![MyRc::clone(&leaf)]),
+                });
+                let std_branch = Rc::new(StdNode {
+                    parent: RefCell::new(Weak::new()),
+                    children: RefCell::new(vec![Rc::clone(&std_leaf)]),
+                });
+                *leaf.parent.borrow_mut() = MyRc::downgrade(&branch);
+                *std_leaf.parent.borrow_mut() = Rc::downgrade(&std_branch);
+                assert_counts!(&branch, &std_branch, 1, 1);
+                assert_counts!(&leaf, &std_leaf, 2, 0);
+
+                let parent = leaf.parent.borrow().upgrade().unwrap();
+                let std_parent = std_leaf.parent.borrow().upgrade().unwrap();
+                assert_eq!(parent.value, 5);
+                assert_eq!(parent.children.borrow()[0].value, 3);
+                assert_eq!(std_parent.children.borrow().len(), 1);
+                assert_counts!(&branch, &std_branch, 2, 1);
+
+                let weak = leaf.parent.borrow().clone();
+                let std_weak = std_leaf.parent.borrow().clone();
+                assert_eq!(
+                    (weak.strong_count(), weak.weak_count()),
+                    (std_weak.strong_count(), std_weak.weak_count())
+                );
+                assert_counts!(&branch, &std_branch, 2, 2);
+            }
+
+            assert!(leaf.parent.borrow().upgrade().is_none());
+            assert!(std_leaf.parent.borrow().upgrade().is_none());
+            assert_eq!(
+                leaf.parent.borrow().strong_count(),
+                std_leaf.parent.borrow().strong_count()
+            );
+            assert_eq!(
+                leaf.parent.borrow().weak_count(),
+                std_leaf.parent.borrow().weak_count()
+            );
+            assert_counts!(&leaf, &std_leaf, 1, 0);
+        }
+
+        pub fn drop_example() {
+            use super::drop_tracker::DropTracker;
+
+            let tracker = DropTracker::new();
+            let a = MyRc::new(tracker.track("a", ()));
+            let weak = MyRc::downgrade(&a);
+            let b = MyRc::clone(&a);
+            drop(a);
+            assert!(tracker.dropped().is_empty());
+            drop(b);
+            //The value goes with the last strong reference, the block stays
+            // for the weak one.
+            assert_eq!(tracker.take(), ["a"]);
+            assert!(weak.upgrade().is_none());
+            let weak2 = weak.clone();
+            drop(weak);
+            drop(weak2);
+
+            let weak = {
+                let c = MyRc::new(tracker.track("c", ()));
+                MyRc::downgrade(&c)
+            };
+            assert_eq!(tracker.take(), ["c"]);
+            assert_eq!((weak.strong_count(), weak.weak_count()), (0, 0));
+            assert!(MyWeak::<()>::new().upgrade().is_none());
+        }
+    }
     //-Process Test-----------------------------------------------------------/
     #[test]
     pub fn process_test() {